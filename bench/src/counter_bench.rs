@@ -0,0 +1,503 @@
+// Every counter instruction, plus increments through the optional rate limit, fee,
+// multisig, delegate, token gate and shard paths, and through the Borsh layout
+
+use crate::{Bench, Measurement};
+use counter::{
+    client, find_program_data_address, CounterAccount, CounterFee, RateLimit, TokenAccount,
+    TokenGate, WindowUnit, BPF_LOADER_UPGRADEABLE_ID, TOKEN_PROGRAM_ID,
+};
+use solana_program_test::ProgramTest;
+use solana_sdk::{
+    account::Account, pubkey::Pubkey, rent::Rent, signature::Signer,
+    signer::keypair::keypair_from_seed, system_program,
+};
+
+const PROGRAM_ID: Pubkey = Pubkey::new_from_array([1; 32]);
+// The build with the `borsh-increment` feature, to compare the counter layouts
+const BORSH_PROGRAM_ID: Pubkey = Pubkey::new_from_array([8; 32]);
+
+pub async fn run() -> Vec<Measurement> {
+    let authority = keypair_from_seed(&[1; 32]).unwrap();
+    let contributor = keypair_from_seed(&[2; 32]).unwrap();
+    let new_authority = keypair_from_seed(&[3; 32]).unwrap();
+    let legacy_counter = keypair_from_seed(&[4; 32]).unwrap();
+    let treasury = Pubkey::new_from_array([5; 32]);
+    let gate_mint = Pubkey::new_from_array([6; 32]);
+    let token_account = Pubkey::new_from_array([7; 32]);
+
+    let mut program_test = ProgramTest::new("counter", PROGRAM_ID, None);
+    program_test.add_program("counter_borsh", BORSH_PROGRAM_ID, None);
+    program_test.prefer_bpf(true);
+    for funded in [authority.pubkey(), contributor.pubkey(), treasury] {
+        program_test.add_account(
+            funded,
+            Account::new(10_000_000_000, 0, &system_program::id()),
+        );
+    }
+    // The authority deployed the program, so it may create the config. The loader's
+    // program data header is a u32 tag of 3, the deploy slot and the authority option.
+    let mut program_data = 3u32.to_le_bytes().to_vec();
+    program_data.extend_from_slice(&0u64.to_le_bytes());
+    program_data.push(1);
+    program_data.extend_from_slice(authority.pubkey().as_ref());
+    program_test.add_account(
+        find_program_data_address(&PROGRAM_ID).0,
+        Account {
+            lamports: Rent::default().minimum_balance(program_data.len()),
+            data: program_data,
+            owner: BPF_LOADER_UPGRADEABLE_ID,
+            ..Account::default()
+        },
+    );
+    // A counter from before accounts were versioned, a bare u64 count
+    program_test.add_account(
+        legacy_counter.pubkey(),
+        Account {
+            lamports: Rent::default().minimum_balance(CounterAccount::LEGACY_LEN),
+            data: 42u64.to_le_bytes().to_vec(),
+            owner: PROGRAM_ID,
+            ..Account::default()
+        },
+    );
+    // The authority's initialized account of the gate token, in the SPL Token layout
+    let mut token_data = vec![0; TokenAccount::LEN];
+    token_data[..32].copy_from_slice(gate_mint.as_ref());
+    token_data[32..64].copy_from_slice(authority.pubkey().as_ref());
+    token_data[64..72].copy_from_slice(&1_000u64.to_le_bytes());
+    token_data[108] = 1;
+    program_test.add_account(
+        token_account,
+        Account {
+            lamports: Rent::default().minimum_balance(TokenAccount::LEN),
+            data: token_data,
+            owner: TOKEN_PROGRAM_ID,
+            ..Account::default()
+        },
+    );
+    let mut bench = Bench::start(program_test).await;
+    let payer = bench.payer().pubkey();
+    let counter = client::counter_address(&PROGRAM_ID, &authority.pubkey(), 0);
+    let sharded_counter = client::counter_address(&PROGRAM_ID, &authority.pubkey(), 1);
+    let borsh_counter = client::counter_address(&BORSH_PROGRAM_ID, &authority.pubkey(), 0);
+
+    // Program config
+    bench
+        .measure(
+            "counter/InitializeConfig",
+            client::initialize_config(&PROGRAM_ID, &authority.pubkey(), 1),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/UpdateConfig",
+            client::update_config(&PROGRAM_ID, &authority.pubkey(), authority.pubkey(), 1),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/SetPaused",
+            client::set_paused(&PROGRAM_ID, &authority.pubkey(), true),
+            &[&authority],
+        )
+        .await;
+    bench
+        .run(
+            client::set_paused(&PROGRAM_ID, &authority.pubkey(), false),
+            &[&authority],
+        )
+        .await;
+
+    // Counter lifecycle and arithmetic
+    bench
+        .measure(
+            "counter/InitializeCounter",
+            client::initialize_counter(&PROGRAM_ID, &payer, &authority.pubkey(), 0, 100, None),
+            &[&authority],
+        )
+        .await;
+    let updates = [
+        (
+            "counter/IncrementCounter",
+            client::increment(&PROGRAM_ID, &counter, &authority.pubkey()),
+        ),
+        (
+            "counter/IncrementBy",
+            client::increment_by(&PROGRAM_ID, &counter, &authority.pubkey(), 5),
+        ),
+        (
+            "counter/Decrement",
+            client::decrement(&PROGRAM_ID, &counter, &authority.pubkey()),
+        ),
+        (
+            "counter/DecrementBy",
+            client::decrement_by(&PROGRAM_ID, &counter, &authority.pubkey(), 5),
+        ),
+        (
+            "counter/SetValue",
+            client::set_value(&PROGRAM_ID, &counter, &authority.pubkey(), 200),
+        ),
+        (
+            "counter/Reset",
+            client::reset(&PROGRAM_ID, &counter, &authority.pubkey()),
+        ),
+        (
+            "counter/Freeze",
+            client::freeze(&PROGRAM_ID, &counter, &authority.pubkey()),
+        ),
+        (
+            "counter/Thaw",
+            client::thaw(&PROGRAM_ID, &counter, &authority.pubkey()),
+        ),
+    ];
+    for (name, instruction) in updates {
+        bench.measure(name, instruction, &[&authority]).await;
+    }
+    // The same IncrementBy, after the same single increment, through a Borsh round trip
+    // of the whole counter instead of the zero-copy layout
+    for instruction in [
+        client::initialize_counter(&BORSH_PROGRAM_ID, &payer, &authority.pubkey(), 0, 100, None),
+        client::increment(&BORSH_PROGRAM_ID, &borsh_counter, &authority.pubkey()),
+    ] {
+        bench.run(instruction, &[&authority]).await;
+    }
+    bench
+        .measure(
+            "counter/IncrementBy, Borsh layout",
+            client::increment_by(&BORSH_PROGRAM_ID, &borsh_counter, &authority.pubkey(), 5),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/GetCounter",
+            client::get_counter(&PROGRAM_ID, &counter),
+            &[],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/GetHistory",
+            client::get_history(&PROGRAM_ID, &counter),
+            &[],
+        )
+        .await;
+
+    // Rate limited increments also track the current window
+    let rate_limit = RateLimit {
+        max_increments: 1_000,
+        window: 1_000,
+        unit: WindowUnit::Slots,
+    };
+    bench
+        .measure(
+            "counter/SetRateLimit",
+            client::set_rate_limit(&PROGRAM_ID, &counter, &authority.pubkey(), Some(rate_limit)),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/IncrementCounter, rate limited",
+            client::increment(&PROGRAM_ID, &counter, &authority.pubkey()),
+            &[&authority],
+        )
+        .await;
+    bench
+        .run(
+            client::set_rate_limit(&PROGRAM_ID, &counter, &authority.pubkey(), None),
+            &[&authority],
+        )
+        .await;
+
+    // Paid increments transfer the fee through a CPI
+    let fee = CounterFee {
+        price: 1_000,
+        treasury,
+    };
+    bench
+        .measure(
+            "counter/SetFee",
+            client::set_fee(&PROGRAM_ID, &counter, &authority.pubkey(), Some(fee)),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/IncrementCounter, with fee",
+            client::with_fee(
+                client::increment(&PROGRAM_ID, &counter, &authority.pubkey()),
+                &treasury,
+            ),
+            &[&authority],
+        )
+        .await;
+    bench
+        .run(
+            client::set_fee(&PROGRAM_ID, &counter, &authority.pubkey(), None),
+            &[&authority],
+        )
+        .await;
+
+    // The first contribution creates the contributor's record
+    bench
+        .measure(
+            "counter/SetShared",
+            client::set_shared(&PROGRAM_ID, &counter, &authority.pubkey(), true),
+            &[&authority],
+        )
+        .await;
+    for name in ["counter/Contribute, new contributor", "counter/Contribute"] {
+        bench
+            .measure(
+                name,
+                client::contribute(&PROGRAM_ID, &counter, &contributor.pubkey(), 1),
+                &[&contributor],
+            )
+            .await;
+    }
+    bench
+        .measure(
+            "counter/CloseContribution",
+            client::close_contribution(
+                &PROGRAM_ID,
+                &counter,
+                &contributor.pubkey(),
+                &contributor.pubkey(),
+            ),
+            &[&contributor],
+        )
+        .await;
+    // Under a multisig every registered signer is looked for among the accounts
+    bench
+        .measure(
+            "counter/AddSigner",
+            client::add_signer(
+                &PROGRAM_ID,
+                &counter,
+                &authority.pubkey(),
+                authority.pubkey(),
+            ),
+            &[&authority],
+        )
+        .await;
+    bench
+        .run(
+            client::add_signer(
+                &PROGRAM_ID,
+                &counter,
+                &authority.pubkey(),
+                new_authority.pubkey(),
+            ),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/SetThreshold",
+            client::set_threshold(&PROGRAM_ID, &counter, &authority.pubkey(), 2),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/IncrementCounter, 2 of 2 multisig",
+            client::with_signers(
+                client::increment(&PROGRAM_ID, &counter, &authority.pubkey()),
+                &[new_authority.pubkey()],
+            ),
+            &[&authority, &new_authority],
+        )
+        .await;
+    bench
+        .run(
+            client::with_signers(
+                client::set_threshold(&PROGRAM_ID, &counter, &authority.pubkey(), 1),
+                &[new_authority.pubkey()],
+            ),
+            &[&authority, &new_authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/RemoveSigner",
+            client::remove_signer(
+                &PROGRAM_ID,
+                &counter,
+                &authority.pubkey(),
+                new_authority.pubkey(),
+            ),
+            &[&authority],
+        )
+        .await;
+    // A delegate's increment also finds and updates its record
+    bench
+        .measure(
+            "counter/ApproveDelegate",
+            client::approve_delegate(
+                &PROGRAM_ID,
+                &payer,
+                &counter,
+                &authority.pubkey(),
+                &contributor.pubkey(),
+                Some(1_000),
+                None,
+            ),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/IncrementCounter, delegate",
+            client::with_delegate(
+                client::increment(&PROGRAM_ID, &counter, &contributor.pubkey()),
+                &counter,
+            ),
+            &[&contributor],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/RevokeDelegate",
+            client::revoke_delegate(
+                &PROGRAM_ID,
+                &counter,
+                &authority.pubkey(),
+                &contributor.pubkey(),
+                &payer,
+            ),
+            &[&authority],
+        )
+        .await;
+    // Gated increments look for the signer's token account
+    let gate = TokenGate {
+        mint: gate_mint,
+        min_balance: 1,
+    };
+    bench
+        .measure(
+            "counter/SetTokenGate",
+            client::set_token_gate(&PROGRAM_ID, &counter, &authority.pubkey(), Some(gate)),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/IncrementCounter, token gated",
+            client::with_token_account(
+                client::increment(&PROGRAM_ID, &counter, &authority.pubkey()),
+                &token_account,
+            ),
+            &[&authority],
+        )
+        .await;
+    bench
+        .run(
+            client::set_token_gate(&PROGRAM_ID, &counter, &authority.pubkey(), None),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/CloseCounter",
+            client::close_counter(&PROGRAM_ID, &counter, &authority.pubkey(), &payer),
+            &[&authority],
+        )
+        .await;
+
+    // Sharded counters
+    bench
+        .run(
+            client::initialize_counter(&PROGRAM_ID, &payer, &authority.pubkey(), 1, 0, None),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/InitializeShard",
+            client::initialize_shard(
+                &PROGRAM_ID,
+                &payer,
+                &sharded_counter,
+                &authority.pubkey(),
+                0,
+            ),
+            &[&authority],
+        )
+        .await;
+    bench
+        .run(
+            client::initialize_shard(
+                &PROGRAM_ID,
+                &payer,
+                &sharded_counter,
+                &authority.pubkey(),
+                1,
+            ),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/IncrementCounter, shard",
+            client::increment_shard(&PROGRAM_ID, &sharded_counter, 0, &authority.pubkey()),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/IncrementBy, shard",
+            client::increment_shard_by(&PROGRAM_ID, &sharded_counter, 1, &authority.pubkey(), 5),
+            &[&authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/Aggregate, 2 shards",
+            client::aggregate(&PROGRAM_ID, &sharded_counter, 2),
+            &[],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/CloseCounter, 2 shards",
+            client::close_sharded_counter(
+                &PROGRAM_ID,
+                &sharded_counter,
+                &authority.pubkey(),
+                &payer,
+                2,
+            ),
+            &[&authority],
+        )
+        .await;
+
+    // Legacy counters are grown to the current layout
+    bench
+        .measure(
+            "counter/MigrateCounter, legacy",
+            client::migrate_legacy_counter(
+                &PROGRAM_ID,
+                &legacy_counter.pubkey(),
+                &authority.pubkey(),
+                &payer,
+            ),
+            &[&legacy_counter, &authority],
+        )
+        .await;
+    bench
+        .measure(
+            "counter/SetAuthority",
+            client::set_authority(
+                &PROGRAM_ID,
+                &legacy_counter.pubkey(),
+                &authority.pubkey(),
+                Some(new_authority.pubkey()),
+            ),
+            &[&authority],
+        )
+        .await;
+
+    bench.into_measurements()
+}
@@ -0,0 +1,119 @@
+// Compute-unit benchmarks for the counter and todo programs.
+//
+// Every instruction of both programs runs against the SBF builds in solana-program-test,
+// and the compute units each one consumes are written to report.md next to the stored
+// baseline. Program ids and signers are fixed, so PDA bump searches cost the same on
+// every run and the numbers only move when the programs do.
+
+mod counter_bench;
+mod report;
+mod todo_bench;
+
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+// Compute units one benchmark consumed, or why its transaction failed
+pub struct Measurement {
+    pub name: String,
+    pub units: Result<u64, String>,
+}
+
+// A running program test that records the instructions it measures
+pub struct Bench {
+    context: ProgramTestContext,
+    measurements: Vec<Measurement>,
+}
+
+impl Bench {
+    pub async fn start(program_test: ProgramTest) -> Self {
+        Self {
+            context: program_test.start_with_context().await,
+            measurements: vec![],
+        }
+    }
+
+    // Pays the transaction fees
+    pub fn payer(&self) -> &Keypair {
+        &self.context.payer
+    }
+
+    // Run `instruction` and record its compute units as `name`. Failures are recorded
+    // too, the run carries on with the state the failed transaction left.
+    pub async fn measure(&mut self, name: &str, instruction: Instruction, signers: &[&Keypair]) {
+        let (result, units) = self.execute(instruction, signers).await;
+        self.measurements.push(Measurement {
+            name: name.to_string(),
+            units: result.map(|()| units).map_err(|error| error.to_string()),
+        });
+    }
+
+    // Run a setup instruction that is not measured, it has to succeed
+    pub async fn run(&mut self, instruction: Instruction, signers: &[&Keypair]) {
+        let (result, _units) = self.execute(instruction, signers).await;
+        result.expect("Setup instruction failed");
+    }
+
+    async fn execute(
+        &mut self,
+        instruction: Instruction,
+        signers: &[&Keypair],
+    ) -> (Result<(), TransactionError>, u64) {
+        // Repeating an instruction in the same block would be refused as a duplicate
+        let blockhash = self
+            .context
+            .get_new_latest_blockhash()
+            .await
+            .expect("Failed to get a new blockhash");
+        let payer = &self.context.payer;
+        let mut all_signers = vec![payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+
+        let result = self
+            .context
+            .banks_client
+            .process_transaction_with_metadata(transaction)
+            .await
+            .expect("Failed to process transaction");
+        let units = result
+            .metadata
+            .map_or(0, |metadata| metadata.compute_units_consumed);
+        (result.result, units)
+    }
+
+    pub fn into_measurements(self) -> Vec<Measurement> {
+        self.measurements
+    }
+}
+
+fn main() {
+    let save = std::env::args().skip(1).any(|arg| arg == "--save");
+
+    // Where `cargo build-sbf --sbf-out-dir target/deploy` leaves the programs
+    if std::env::var_os("SBF_OUT_DIR").is_none() {
+        std::env::set_var(
+            "SBF_OUT_DIR",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/target/deploy"),
+        );
+    }
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start the runtime");
+    let mut measurements = runtime.block_on(counter_bench::run());
+    measurements.extend(runtime.block_on(todo_bench::run()));
+
+    let regressions = report::write(&measurements, save);
+    if regressions > 0 {
+        eprintln!("❌ {regressions} benchmarks regressed or failed, see report.md");
+        std::process::exit(1);
+    }
+    println!("✅ {} benchmarks within the baseline", measurements.len());
+}
@@ -0,0 +1,84 @@
+// The report and the baseline it compares against
+
+use crate::Measurement;
+use std::{collections::BTreeMap, fmt::Write, fs};
+
+const REPORT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/report.md");
+const BASELINE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/baseline.txt");
+
+// How much more than its baseline a benchmark may consume before it counts as a
+// regression. Compute units are the same on every run of a build, this leaves room for
+// toolchain and dependency updates.
+const TOLERANCE: f64 = 0.02;
+
+const HEADER: &str = "# Compute units
+
+Compute units each instruction consumed, from `cargo run --release` in `bench/`.
+Changes are against `baseline.txt`, more than 2% over it is a regression. A benchmark
+that fails counts against the run.
+
+| Benchmark | Compute units | Baseline | Change |
+| --- | ---: | ---: | ---: |
+";
+
+// Write report.md, and with `save` store the results as the new baseline. Returns the
+// number of regressions and failures.
+pub fn write(measurements: &[Measurement], save: bool) -> usize {
+    let baseline = read_baseline();
+    let mut report = HEADER.to_string();
+    let mut regressions = 0;
+    for measurement in measurements {
+        let name = &measurement.name;
+        let baseline_units = baseline.get(name).copied();
+        let (units, change, regressed) = match (&measurement.units, baseline_units) {
+            (Ok(units), Some(baseline_units)) => {
+                let change = (*units as f64 - baseline_units as f64) / baseline_units.max(1) as f64;
+                (
+                    units.to_string(),
+                    format!("{:+.1}%", change * 100.0),
+                    change > TOLERANCE,
+                )
+            }
+            (Ok(units), None) => (units.to_string(), "new".to_string(), false),
+            (Err(error), _) => (format!("failed: {error}"), "-".to_string(), true),
+        };
+        let baseline_units = baseline_units.map_or("-".to_string(), |units| units.to_string());
+        let change = if regressed {
+            regressions += 1;
+            eprintln!("{name}: {units}, baseline {baseline_units}");
+            format!("{change} ❌")
+        } else {
+            change
+        };
+        writeln!(report, "| {name} | {units} | {baseline_units} | {change} |").unwrap();
+    }
+    fs::write(REPORT, report).expect("Failed to write report.md");
+
+    if save {
+        let mut baseline =
+            "# Compute units per benchmark, from `cargo run --release -- --save`\n".to_string();
+        for measurement in measurements {
+            if let Ok(units) = measurement.units {
+                writeln!(baseline, "{units} {}", measurement.name).unwrap();
+            }
+        }
+        fs::write(BASELINE, baseline).expect("Failed to write baseline.txt");
+    }
+    regressions
+}
+
+// Benchmark names and their compute units, one per line after the units. Empty until a
+// baseline has been saved.
+fn read_baseline() -> BTreeMap<String, u64> {
+    let Ok(baseline) = fs::read_to_string(BASELINE) else {
+        return BTreeMap::new();
+    };
+    baseline
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (units, name) = line.split_once(' ')?;
+            Some((name.to_string(), units.parse().ok()?))
+        })
+        .collect()
+}
@@ -0,0 +1,122 @@
+// Both todo instructions at several list sizes. Each call deserializes and reserializes
+// the whole list, so their cost grows with it.
+
+use crate::{Bench, Measurement};
+use solana_program_test::ProgramTest;
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+    sysvar::clock,
+};
+use todo::TodoInstruction;
+
+const PROGRAM_ID: Pubkey = Pubkey::new_from_array([2; 32]);
+
+// Numbers of to-dos in the lists the instructions run on. 32 of the to-dos below take
+// 932 bytes, close to the 1KB the program allocates for a list.
+const LIST_SIZES: [usize; 4] = [1, 8, 16, 32];
+
+// What the program allocates for a list it creates. The lists to add to are allocated
+// the same way, with room to grow.
+const LIST_SPACE: usize = 1024;
+
+pub async fn run() -> Vec<Measurement> {
+    // Two copies of each list, one to add to and one to mark done in
+    let lists: Vec<(usize, Pubkey, Pubkey)> = LIST_SIZES
+        .iter()
+        .enumerate()
+        .map(|(i, &size)| {
+            let seed = 2 * i as u8 + 1;
+            (
+                size,
+                Pubkey::new_from_array([seed; 32]),
+                Pubkey::new_from_array([seed + 1; 32]),
+            )
+        })
+        .collect();
+
+    let mut program_test = ProgramTest::new("todo", PROGRAM_ID, None);
+    program_test.prefer_bpf(true);
+    for &(size, new_todo_list, mark_done_list) in &lists {
+        program_test.add_account(new_todo_list, todo_list(size, LIST_SPACE));
+        // Marking a to-do done keeps the list's length, so it fits exactly
+        program_test.add_account(mark_done_list, todo_list(size, 0));
+    }
+    let mut bench = Bench::start(program_test).await;
+    let payer = bench.payer().pubkey();
+
+    // The first to-do creates the list account
+    let new_list = Keypair::new();
+    bench
+        .measure(
+            "todo/NewTodo, new list",
+            new_todo(&new_list.pubkey(), true, &payer, todo_name(0)),
+            &[&new_list],
+        )
+        .await;
+
+    for (size, new_todo_list, mark_done_list) in lists {
+        bench
+            .measure(
+                &format!("todo/NewTodo, {size} todos"),
+                new_todo(&new_todo_list, false, &payer, todo_name(size)),
+                &[],
+            )
+            .await;
+        // The last to-do, found after comparing every name before it
+        bench
+            .measure(
+                &format!("todo/MarkDone, {size} todos"),
+                mark_done(&mark_done_list, todo_name(size - 1)),
+                &[],
+            )
+            .await;
+    }
+
+    bench.into_measurements()
+}
+
+fn todo_name(i: usize) -> String {
+    format!("To-do number {i:03}")
+}
+
+// A list account holding `size` to-dos that are not done yet, zero padded to `space`
+// bytes if they take fewer. The Borsh encoding of a `TodoAccount` is that of its list of
+// (name, done, publish date) fields.
+fn todo_list(size: usize, space: usize) -> Account {
+    let todos: Vec<(String, bool, u64)> = (0..size).map(|i| (todo_name(i), false, 0)).collect();
+    let mut data = borsh::to_vec(&todos).unwrap();
+    data.resize(data.len().max(space), 0);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: PROGRAM_ID,
+        ..Account::default()
+    }
+}
+
+// Only a list that does not exist yet signs, to be created
+fn new_todo(list: &Pubkey, list_signs: bool, payer: &Pubkey, todo: String) -> Instruction {
+    Instruction::new_with_borsh(
+        PROGRAM_ID,
+        &TodoInstruction::NewTodo { todo },
+        vec![
+            AccountMeta::new(*list, list_signs),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(clock::id(), false),
+        ],
+    )
+}
+
+fn mark_done(list: &Pubkey, todo: String) -> Instruction {
+    Instruction::new_with_borsh(
+        PROGRAM_ID,
+        &TodoInstruction::MarkDone { todo },
+        vec![AccountMeta::new(*list, false)],
+    )
+}
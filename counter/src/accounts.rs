@@ -0,0 +1,231 @@
+// Account lists of the counter program's instructions, shared by the off-chain
+// instruction builders in `client` and the CPI helpers in `cpi`.
+//
+// Everything here takes the addresses as given, deriving them is up to the caller:
+// `client` derives PDAs from the program id, `cpi` takes the keys of the accounts
+// the calling program was passed.
+
+use solana_program::{instruction::AccountMeta, pubkey::Pubkey};
+
+pub(crate) fn initialize_counter(
+    counter: &Pubkey,
+    payer: &Pubkey,
+    authority: &Pubkey,
+    config: &Pubkey,
+    generation: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*counter, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(solana_program::system_program::ID, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(*config, false),
+        AccountMeta::new(*generation, false),
+    ]
+}
+
+// Accounts of every instruction that changes a single counter
+pub(crate) fn update_counter(
+    counter: &Pubkey,
+    authority: &Pubkey,
+    config: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*counter, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(*config, false),
+    ]
+}
+
+pub(crate) fn update_shard(
+    shard: &Pubkey,
+    authority: &Pubkey,
+    config: &Pubkey,
+    counter: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*shard, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(*config, false),
+        AccountMeta::new_readonly(*counter, false),
+    ]
+}
+
+pub(crate) fn initialize_shard(
+    shard: &Pubkey,
+    counter: &Pubkey,
+    payer: &Pubkey,
+    authority: &Pubkey,
+    config: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*shard, false),
+        AccountMeta::new(*counter, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(solana_program::system_program::ID, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(*config, false),
+    ]
+}
+
+pub(crate) fn aggregate(counter: &Pubkey, config: &Pubkey, shards: &[Pubkey]) -> Vec<AccountMeta> {
+    let mut accounts = vec![
+        AccountMeta::new(*counter, false),
+        AccountMeta::new_readonly(*config, false),
+    ];
+    accounts.extend(shards.iter().map(|shard| AccountMeta::new(*shard, false)));
+    accounts
+}
+
+pub(crate) fn contribute(
+    counter: &Pubkey,
+    contributor: &Pubkey,
+    contribution: &Pubkey,
+    config: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*counter, false),
+        AccountMeta::new(*contributor, true),
+        AccountMeta::new(*contribution, false),
+        AccountMeta::new_readonly(solana_program::system_program::ID, false),
+        AccountMeta::new_readonly(*config, false),
+    ]
+}
+
+pub(crate) fn close_contribution(
+    contribution: &Pubkey,
+    contributor: &Pubkey,
+    destination: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*contribution, false),
+        AccountMeta::new_readonly(*contributor, true),
+        AccountMeta::new(*destination, false),
+    ]
+}
+
+pub(crate) fn approve_delegate(
+    delegate_record: &Pubkey,
+    counter: &Pubkey,
+    payer: &Pubkey,
+    authority: &Pubkey,
+    config: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*delegate_record, false),
+        AccountMeta::new_readonly(*counter, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(solana_program::system_program::ID, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(*config, false),
+    ]
+}
+
+pub(crate) fn revoke_delegate(
+    delegate_record: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+    config: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*delegate_record, false),
+        AccountMeta::new_readonly(*counter, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new_readonly(*config, false),
+    ]
+}
+
+pub(crate) fn close_counter(
+    counter: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+    config: &Pubkey,
+    shards: &[Pubkey],
+) -> Vec<AccountMeta> {
+    let mut accounts = vec![
+        AccountMeta::new(*counter, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new_readonly(*config, false),
+    ];
+    accounts.extend(shards.iter().map(|shard| AccountMeta::new(*shard, false)));
+    accounts
+}
+
+// A legacy counter is upgraded by its keypair, so then both it and the new authority
+// sign
+pub(crate) fn migrate_counter(
+    counter: &Pubkey,
+    counter_signs: bool,
+    authority: &Pubkey,
+    authority_signs: bool,
+    payer: &Pubkey,
+    config: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*counter, counter_signs),
+        AccountMeta::new_readonly(*authority, authority_signs),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(solana_program::system_program::ID, false),
+        AccountMeta::new_readonly(*config, false),
+    ]
+}
+
+// Accounts of GetCounter and GetHistory
+pub(crate) fn read_counter(counter: &Pubkey) -> Vec<AccountMeta> {
+    vec![AccountMeta::new_readonly(*counter, false)]
+}
+
+pub(crate) fn initialize_config(
+    config: &Pubkey,
+    admin: &Pubkey,
+    program_data: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*config, false),
+        AccountMeta::new(*admin, true),
+        AccountMeta::new_readonly(solana_program::system_program::ID, false),
+        AccountMeta::new_readonly(*program_data, false),
+    ]
+}
+
+// Accounts of every instruction that changes the program config
+pub(crate) fn update_config(config: &Pubkey, admin: &Pubkey) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*config, false),
+        AccountMeta::new_readonly(*admin, true),
+    ]
+}
+
+// The accounts that pay the fee of an increment directly follow the instruction's own
+// accounts. The signer at index 1 pays, so it becomes writable.
+pub(crate) fn add_fee(accounts: &mut Vec<AccountMeta>, treasury: &Pubkey) {
+    accounts[1].is_writable = true;
+    accounts.extend([
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new_readonly(solana_program::system_program::ID, false),
+    ]);
+}
+
+// The program looks up the accounts added below by key, so they go after the fee
+// accounts in any order
+pub(crate) fn add_signers<'a>(
+    accounts: &mut Vec<AccountMeta>,
+    signers: impl IntoIterator<Item = &'a Pubkey>,
+) {
+    accounts.extend(
+        signers
+            .into_iter()
+            .map(|signer| AccountMeta::new_readonly(*signer, true)),
+    );
+}
+
+pub(crate) fn add_delegate_record(accounts: &mut Vec<AccountMeta>, delegate_record: &Pubkey) {
+    accounts.push(AccountMeta::new(*delegate_record, false));
+}
+
+pub(crate) fn add_token_account(accounts: &mut Vec<AccountMeta>, token_account: &Pubkey) {
+    accounts.push(AccountMeta::new_readonly(*token_account, false));
+}
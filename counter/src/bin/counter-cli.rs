@@ -0,0 +1,616 @@
+// Command-line tool to inspect and manage counters, e.g. against a local test validator:
+//
+//   counter-cli --program-id <PROGRAM_ID> init --index 0 --value 10
+//   counter-cli --program-id <PROGRAM_ID> increment <COUNTER> --by 5
+//   counter-cli --program-id <PROGRAM_ID> list
+//
+// Changes to a multisig counter are signed by the keypair and each --signer keypair.
+// A key approved with `approve` increments with `increment --as-delegate` as its keypair.
+// Increments of a token gated counter pass the keypair's token account with --token-account.
+// A counter from before accounts were versioned is migrated with `migrate --counter-keypair`.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use counter::{
+    client, CounterAccount, CounterBounds, CounterError, CounterFee, OverflowPolicy, RateLimit,
+    TokenGate, WindowUnit,
+};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use std::{error::Error, process::exit};
+
+#[derive(Parser)]
+#[command(name = "counter-cli", about = "Manage counters of the counter program")]
+struct Cli {
+    #[arg(
+        long,
+        short,
+        default_value = "http://localhost:8899",
+        help = "RPC endpoint, a local test validator by default"
+    )]
+    url: String,
+
+    #[arg(
+        long,
+        short,
+        help = "Keypair paying for transactions and acting as counter authority [default: ~/.config/solana/id.json]"
+    )]
+    keypair: Option<String>,
+
+    #[arg(
+        long = "signer",
+        help = "Keypair of another signer of a multisig counter, repeat for each one"
+    )]
+    signers: Vec<String>,
+
+    #[arg(long, help = "Address of the deployed counter program")]
+    program_id: Pubkey,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Create the keypair's counter at --index")]
+    Init {
+        #[arg(long, default_value_t = 0)]
+        index: u64,
+        #[arg(long, default_value_t = 0)]
+        value: u64,
+        #[arg(long)]
+        min: Option<u64>,
+        #[arg(long)]
+        max: Option<u64>,
+        #[arg(long, value_enum)]
+        policy: Option<Policy>,
+    },
+    #[command(about = "Add the program's default step, or --by an amount")]
+    Increment {
+        counter: Pubkey,
+        #[arg(long)]
+        by: Option<u64>,
+        #[arg(
+            long,
+            help = "Increment as an approved delegate instead of the authority"
+        )]
+        as_delegate: bool,
+        #[arg(long, help = "The keypair's account of the counter's gate token")]
+        token_account: Option<Pubkey>,
+    },
+    #[command(about = "Subtract the program's default step, or --by an amount")]
+    Decrement {
+        counter: Pubkey,
+        #[arg(long)]
+        by: Option<u64>,
+    },
+    #[command(about = "Overwrite the count")]
+    Set { counter: Pubkey, value: u64 },
+    #[command(about = "Reject changes to the counter until it is thawed")]
+    Freeze { counter: Pubkey },
+    #[command(about = "Allow changes to a frozen counter again")]
+    Thaw { counter: Pubkey },
+    #[command(about = "Charge --price lamports per increment to --treasury, or make them free")]
+    Fee {
+        counter: Pubkey,
+        #[arg(long, requires = "treasury")]
+        price: Option<u64>,
+        #[arg(long, requires = "price")]
+        treasury: Option<Pubkey>,
+    },
+    #[command(about = "Let anyone contribute to the counter")]
+    Share { counter: Pubkey },
+    #[command(about = "Only let the authority contribute to the counter again")]
+    Unshare { counter: Pubkey },
+    #[command(about = "Increment a counter and credit the amount to the keypair")]
+    Contribute {
+        counter: Pubkey,
+        amount: u64,
+        #[arg(long, help = "The keypair's account of the counter's gate token")]
+        token_account: Option<Pubkey>,
+    },
+    #[command(
+        about = "Close the keypair's contribution record, the rent goes to --destination or the keypair"
+    )]
+    CloseContribution {
+        counter: Pubkey,
+        #[arg(long)]
+        destination: Option<Pubkey>,
+    },
+    #[command(about = "Print the largest contributors to a counter")]
+    Top {
+        counter: Pubkey,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    #[command(
+        about = "Allow at most --max increments per --window, or lift the limit without them"
+    )]
+    RateLimit {
+        counter: Pubkey,
+        #[arg(long, requires = "window")]
+        max: Option<u64>,
+        #[arg(long, requires = "max")]
+        window: Option<u64>,
+        #[arg(long, value_enum, default_value_t = Unit::Slots, help = "What the window is measured in")]
+        unit: Unit,
+    },
+    #[command(about = "Register a signer of the counter's multisig")]
+    AddSigner { counter: Pubkey, signer: Pubkey },
+    #[command(about = "Deregister a signer of the counter's multisig")]
+    RemoveSigner { counter: Pubkey, signer: Pubkey },
+    #[command(
+        about = "Require this many of the counter's signers to sign, 0 for the authority key"
+    )]
+    Threshold { counter: Pubkey, threshold: u8 },
+    #[command(
+        about = "Let a delegate increment the counter up to --allowance times and until --expires-at"
+    )]
+    Approve {
+        counter: Pubkey,
+        delegate: Pubkey,
+        #[arg(long)]
+        allowance: Option<u64>,
+        #[arg(long, help = "Unix time the grant ends at")]
+        expires_at: Option<i64>,
+    },
+    #[command(
+        about = "End a delegate's grant, the rent goes to --destination or the keypair. Once the counter is closed anyone can, with the record's payer as --destination"
+    )]
+    Revoke {
+        counter: Pubkey,
+        delegate: Pubkey,
+        #[arg(long)]
+        destination: Option<Pubkey>,
+    },
+    #[command(about = "Print how often and until when a delegate may still increment a counter")]
+    Delegate { counter: Pubkey, delegate: Pubkey },
+    #[command(
+        about = "Only let holders of --min-balance of --mint increment, or lift the gate without them"
+    )]
+    Gate {
+        counter: Pubkey,
+        #[arg(long, requires = "min_balance")]
+        mint: Option<Pubkey>,
+        #[arg(long, requires = "mint")]
+        min_balance: Option<u64>,
+    },
+    #[command(about = "Close the counter, the rent goes to --destination or the keypair")]
+    Close {
+        counter: Pubkey,
+        #[arg(long)]
+        destination: Option<Pubkey>,
+    },
+    #[command(about = "Upgrade a counter to the current layout, the keypair tops up the rent")]
+    Migrate {
+        counter: Pubkey,
+        #[arg(
+            long,
+            help = "Keypair of a legacy counter, which hands it over to the keypair as authority"
+        )]
+        counter_keypair: Option<String>,
+    },
+    #[command(about = "Print a counter's state")]
+    Show { counter: Pubkey },
+    #[command(about = "Print a counter's recent changes, oldest first")]
+    History { counter: Pubkey },
+    #[command(about = "Fold the pending increments of a sharded counter's shards into it")]
+    Aggregate { counter: Pubkey },
+    #[command(about = "Print every counter of --authority, the keypair by default")]
+    List {
+        #[arg(long)]
+        authority: Option<Pubkey>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Policy {
+    Error,
+    Saturate,
+    Wrap,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Unit {
+    Slots,
+    Seconds,
+}
+
+impl From<Unit> for WindowUnit {
+    fn from(unit: Unit) -> Self {
+        match unit {
+            Unit::Slots => WindowUnit::Slots,
+            Unit::Seconds => WindowUnit::Seconds,
+        }
+    }
+}
+
+impl From<Policy> for OverflowPolicy {
+    fn from(policy: Policy) -> Self {
+        match policy {
+            Policy::Error => OverflowPolicy::Error,
+            Policy::Saturate => OverflowPolicy::Saturate,
+            Policy::Wrap => OverflowPolicy::Wrap,
+        }
+    }
+}
+
+fn main() {
+    if let Err(err) = run(Cli::parse()) {
+        eprintln!("error: {err}");
+        exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let rpc = RpcClient::new_with_commitment(cli.url, CommitmentConfig::confirmed());
+    let keypair_path = match cli.keypair {
+        Some(path) => path,
+        None => default_keypair_path()?,
+    };
+    let keypair = read_keypair_file(&keypair_path)
+        .map_err(|err| format!("failed to read keypair {keypair_path}: {err}"))?;
+    let signers = cli
+        .signers
+        .iter()
+        .map(|path| {
+            read_keypair_file(path).map_err(|err| format!("failed to read keypair {path}: {err}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let program_id = cli.program_id;
+    let authority = keypair.pubkey();
+
+    let instruction = match cli.command {
+        Command::Init {
+            index,
+            value,
+            min,
+            max,
+            policy,
+        } => {
+            let bounds = (min.is_some() || max.is_some() || policy.is_some()).then(|| {
+                let default = CounterBounds::default();
+                CounterBounds {
+                    min: min.unwrap_or(default.min),
+                    max: max.unwrap_or(default.max),
+                    policy: policy.map_or(default.policy, Into::into),
+                }
+            });
+            println!(
+                "Counter: {}",
+                client::counter_address(&program_id, &authority, index)
+            );
+            client::initialize_counter(&program_id, &authority, &authority, index, value, bounds)
+        }
+        Command::Increment {
+            counter,
+            by,
+            as_delegate,
+            token_account,
+        } => {
+            let instruction = match by {
+                None => client::increment(&program_id, &counter, &authority),
+                Some(amount) => client::increment_by(&program_id, &counter, &authority, amount),
+            };
+            let instruction =
+                pass_token_account(pay_fee(&rpc, &counter, instruction)?, token_account);
+            if as_delegate {
+                client::with_delegate(instruction, &counter)
+            } else {
+                instruction
+            }
+        }
+        Command::Decrement { counter, by: None } => {
+            client::decrement(&program_id, &counter, &authority)
+        }
+        Command::Decrement {
+            counter,
+            by: Some(amount),
+        } => client::decrement_by(&program_id, &counter, &authority, amount),
+        Command::Set { counter, value } => {
+            client::set_value(&program_id, &counter, &authority, value)
+        }
+        Command::Freeze { counter } => client::freeze(&program_id, &counter, &authority),
+        Command::Thaw { counter } => client::thaw(&program_id, &counter, &authority),
+        Command::Share { counter } => client::set_shared(&program_id, &counter, &authority, true),
+        Command::Unshare { counter } => {
+            client::set_shared(&program_id, &counter, &authority, false)
+        }
+        Command::Contribute {
+            counter,
+            amount,
+            token_account,
+        } => pass_token_account(
+            pay_fee(
+                &rpc,
+                &counter,
+                client::contribute(&program_id, &counter, &authority, amount),
+            )?,
+            token_account,
+        ),
+        Command::CloseContribution {
+            counter,
+            destination,
+        } => client::close_contribution(
+            &program_id,
+            &counter,
+            &authority,
+            &destination.unwrap_or(authority),
+        ),
+        Command::Fee {
+            counter,
+            price,
+            treasury,
+        } => {
+            let fee = price
+                .zip(treasury)
+                .map(|(price, treasury)| CounterFee { price, treasury });
+            client::set_fee(&program_id, &counter, &authority, fee)
+        }
+        Command::Top { counter, limit } => {
+            let contributors = client::fetch_top_contributors(&rpc, &program_id, &counter, limit)?;
+            if contributors.is_empty() {
+                println!("No contributions to {counter}");
+            }
+            for (rank, (_, contribution)) in contributors.iter().enumerate() {
+                println!(
+                    "{:>3}. {} {} in {} contributions",
+                    rank + 1,
+                    contribution.contributor(),
+                    contribution.total(),
+                    contribution.contributions()
+                );
+            }
+            return Ok(());
+        }
+        Command::RateLimit {
+            counter,
+            max,
+            window,
+            unit,
+        } => {
+            let rate_limit = max.zip(window).map(|(max_increments, window)| RateLimit {
+                max_increments,
+                window,
+                unit: unit.into(),
+            });
+            client::set_rate_limit(&program_id, &counter, &authority, rate_limit)
+        }
+        Command::AddSigner { counter, signer } => {
+            client::add_signer(&program_id, &counter, &authority, signer)
+        }
+        Command::RemoveSigner { counter, signer } => {
+            client::remove_signer(&program_id, &counter, &authority, signer)
+        }
+        Command::Threshold { counter, threshold } => {
+            client::set_threshold(&program_id, &counter, &authority, threshold)
+        }
+        Command::Approve {
+            counter,
+            delegate,
+            allowance,
+            expires_at,
+        } => client::approve_delegate(
+            &program_id,
+            &authority,
+            &counter,
+            &authority,
+            &delegate,
+            allowance,
+            expires_at,
+        ),
+        Command::Revoke {
+            counter,
+            delegate,
+            destination,
+        } => client::revoke_delegate(
+            &program_id,
+            &counter,
+            &authority,
+            &delegate,
+            &destination.unwrap_or(authority),
+        ),
+        Command::Delegate { counter, delegate } => {
+            let delegate_data = client::fetch_delegate(&rpc, &program_id, &counter, &delegate)?;
+            println!("Delegate: {delegate}");
+            println!("  Counter: {counter}");
+            println!("  Payer: {}", delegate_data.payer());
+            match delegate_data.allowance() {
+                Some(allowance) => println!("  Allowance: {allowance} increments"),
+                None => println!("  Allowance: unlimited"),
+            }
+            match delegate_data.expires_at() {
+                Some(expires_at) => println!("  Expires at: {expires_at}"),
+                None => println!("  Expires at: never"),
+            }
+            return Ok(());
+        }
+        Command::Gate {
+            counter,
+            mint,
+            min_balance,
+        } => {
+            let gate = mint
+                .zip(min_balance)
+                .map(|(mint, min_balance)| TokenGate { mint, min_balance });
+            client::set_token_gate(&program_id, &counter, &authority, gate)
+        }
+        Command::Close {
+            counter,
+            destination,
+        } => client::close_sharded_counter(
+            &program_id,
+            &counter,
+            &authority,
+            &destination.unwrap_or(authority),
+            client::fetch_counter(&rpc, &counter)?.shards(),
+        ),
+        Command::Aggregate { counter } => client::aggregate(
+            &program_id,
+            &counter,
+            client::fetch_counter(&rpc, &counter)?.shards(),
+        ),
+        Command::Migrate {
+            counter,
+            counter_keypair: None,
+        } => client::migrate_counter(&program_id, &counter, &authority, &authority),
+        Command::Migrate {
+            counter,
+            counter_keypair: Some(path),
+        } => {
+            let counter_keypair = read_keypair_file(&path)
+                .map_err(|err| format!("failed to read keypair {path}: {err}"))?;
+            if counter_keypair.pubkey() != counter {
+                return Err(format!("{path} is not the keypair of {counter}").into());
+            }
+            // The counter keypair only signs, legacy counters have no multisig
+            let instruction =
+                client::migrate_legacy_counter(&program_id, &counter, &authority, &authority);
+            return send(&rpc, &keypair, &[counter_keypair], instruction);
+        }
+        Command::Show { counter } => {
+            print_counter(&counter, &client::fetch_counter(&rpc, &counter)?);
+            return Ok(());
+        }
+        Command::History { counter } => {
+            let counter_data = client::fetch_counter(&rpc, &counter)?;
+            println!("Counter: {counter}");
+            println!("  Changes: {}", counter_data.changes());
+            for entry in counter_data.history() {
+                println!(
+                    "  slot {} at {}: {:+} to {} by {}",
+                    entry.slot(),
+                    entry.unix_timestamp(),
+                    entry.delta(),
+                    entry.value(),
+                    entry.signer()
+                );
+            }
+            return Ok(());
+        }
+        Command::List { authority: owner } => {
+            let owner = owner.unwrap_or(authority);
+            let counters = client::fetch_counters_by_authority(&rpc, &program_id, &owner)?;
+            if counters.is_empty() {
+                println!("No counters for {owner}");
+            }
+            for (address, counter_data) in &counters {
+                print_counter(address, counter_data);
+            }
+            return Ok(());
+        }
+    };
+
+    // The keypair signs as the authority, the other signers of a multisig counter too
+    let signer_keys: Vec<Pubkey> = signers.iter().map(Signer::pubkey).collect();
+    let instruction = client::with_signers(instruction, &signer_keys);
+    send(&rpc, &keypair, &signers, instruction)
+}
+
+// Sign and send a single instruction, then print the resulting count
+fn send(
+    rpc: &RpcClient,
+    keypair: &Keypair,
+    signers: &[Keypair],
+    instruction: Instruction,
+) -> Result<(), Box<dyn Error>> {
+    let counter = instruction.accounts[0].pubkey;
+    let mut all_signers = vec![keypair];
+    all_signers.extend(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&keypair.pubkey()),
+        &all_signers,
+        rpc.get_latest_blockhash()?,
+    );
+    let signature = rpc
+        .send_and_confirm_transaction(&transaction)
+        .map_err(|err| match err.get_transaction_error() {
+            // Show counter program errors by name instead of a bare code
+            Some(TransactionError::InstructionError(_, InstructionError::Custom(code))) => {
+                match CounterError::from_code(code) {
+                    Some(counter_err) => counter_err.to_string(),
+                    None => err.to_string(),
+                }
+            }
+            _ => err.to_string(),
+        })?;
+    println!("Signature: {signature}");
+
+    // Closed counters have nothing left to show
+    if let Ok(counter_data) = client::fetch_counter(rpc, &counter) {
+        println!("Count: {}", counter_data.count());
+    }
+    Ok(())
+}
+
+// Add the fee accounts to an increment when the counter charges for increments
+fn pay_fee(
+    rpc: &RpcClient,
+    counter: &Pubkey,
+    instruction: Instruction,
+) -> Result<Instruction, Box<dyn Error>> {
+    Ok(match client::fetch_counter(rpc, counter)?.fee() {
+        Some(fee) => client::with_fee(instruction, &fee.treasury),
+        None => instruction,
+    })
+}
+
+// Add the keypair's token account to an increment of a token gated counter
+fn pass_token_account(instruction: Instruction, token_account: Option<Pubkey>) -> Instruction {
+    match token_account {
+        Some(token_account) => client::with_token_account(instruction, &token_account),
+        None => instruction,
+    }
+}
+
+fn print_counter(address: &Pubkey, counter_data: &CounterAccount) {
+    let bounds = counter_data.bounds();
+    println!("Counter: {address}");
+    println!("  Count: {}", counter_data.count());
+    println!("  Authority: {}", counter_data.authority());
+    match counter_data.multisig() {
+        Some(multisig) => println!(
+            "  Multisig: {} of {}",
+            multisig.threshold,
+            multisig.signers.len()
+        ),
+        None => println!("  Multisig: none"),
+    }
+    for signer in counter_data.signers() {
+        println!("    Signer: {signer}");
+    }
+    println!(
+        "  Bounds: [{}, {}] {:?}",
+        bounds.min, bounds.max, bounds.policy
+    );
+    println!("  Frozen: {}", counter_data.is_frozen());
+    println!("  Shards: {}", counter_data.shards());
+    println!("  Shared: {}", counter_data.is_shared());
+    match counter_data.fee() {
+        Some(fee) => println!("  Fee: {} lamports to {}", fee.price, fee.treasury),
+        None => println!("  Fee: none"),
+    }
+    match counter_data.rate_limit() {
+        Some(rate_limit) => println!(
+            "  Rate limit: {} increments per {} {:?}",
+            rate_limit.max_increments, rate_limit.window, rate_limit.unit
+        ),
+        None => println!("  Rate limit: none"),
+    }
+    match counter_data.token_gate() {
+        Some(gate) => println!("  Token gate: {} of {}", gate.min_balance, gate.mint),
+        None => println!("  Token gate: none"),
+    }
+}
+
+// ~/.config/solana/id.json, where the Solana CLI keeps its keypair
+fn default_keypair_path() -> Result<String, Box<dyn Error>> {
+    let home = std::env::var("HOME").map_err(|_| "no --keypair given and HOME is not set")?;
+    Ok(format!("{home}/.config/solana/id.json"))
+}
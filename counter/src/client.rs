@@ -0,0 +1,835 @@
+// Helpers for off-chain clients of the counter program.
+//
+// Instruction builders return ready to sign `Instruction`s with the account list each
+// instruction expects, so callers never lay out instruction bytes or account metas by
+// hand. Counter and config addresses are derived from the program id where the
+// program requires them to be PDAs.
+//
+// Fetching accounts over RPC needs the `client` feature, decoding works without it.
+
+use crate::{
+    accounts, find_config_address, find_contribution_address, find_counter_address,
+    find_delegate_address, find_generation_address, find_program_data_address, find_shard_address,
+    ContributionAccount, CounterAccount, CounterBounds, CounterFee, CounterInstruction,
+    DelegateAccount, ProgramConfig, RateLimit, ShardAccount, TokenGate,
+};
+use solana_program::{instruction::Instruction, program_error::ProgramError, pubkey::Pubkey};
+
+pub use crate::cpi::{decode_count, decode_history};
+
+// Address of the counter `authority` owns at `index`
+pub fn counter_address(program_id: &Pubkey, authority: &Pubkey, index: u64) -> Pubkey {
+    find_counter_address(program_id, authority, index).0
+}
+
+// Address of a counter's shard
+pub fn shard_address(program_id: &Pubkey, counter: &Pubkey, shard: u8) -> Pubkey {
+    find_shard_address(program_id, counter, shard).0
+}
+
+// Address of the record of what `contributor` added to a counter
+pub fn contribution_address(program_id: &Pubkey, counter: &Pubkey, contributor: &Pubkey) -> Pubkey {
+    find_contribution_address(program_id, counter, contributor).0
+}
+
+// Address of the record of what `delegate` may add to a counter
+pub fn delegate_address(program_id: &Pubkey, counter: &Pubkey, delegate: &Pubkey) -> Pubkey {
+    find_delegate_address(program_id, counter, delegate).0
+}
+
+// Address of the program config
+pub fn config_address(program_id: &Pubkey) -> Pubkey {
+    find_config_address(program_id).0
+}
+
+// Address of the account handing out counter generations
+pub fn generation_address(program_id: &Pubkey) -> Pubkey {
+    find_generation_address(program_id).0
+}
+
+// Create the counter `authority` owns at `index`, funded by `payer`
+pub fn initialize_counter(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    authority: &Pubkey,
+    index: u64,
+    initial_value: u64,
+    bounds: Option<CounterBounds>,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::InitializeCounter {
+            initial_value,
+            index,
+            bounds,
+        }
+        .pack(),
+        accounts::initialize_counter(
+            &counter_address(program_id, authority, index),
+            payer,
+            authority,
+            &config_address(program_id),
+            &generation_address(program_id),
+        ),
+    )
+}
+
+pub fn increment(program_id: &Pubkey, counter: &Pubkey, authority: &Pubkey) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::IncrementCounter,
+    )
+}
+
+pub fn increment_by(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::IncrementBy { amount },
+    )
+}
+
+pub fn decrement(program_id: &Pubkey, counter: &Pubkey, authority: &Pubkey) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::Decrement,
+    )
+}
+
+pub fn decrement_by(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::DecrementBy { amount },
+    )
+}
+
+pub fn reset(program_id: &Pubkey, counter: &Pubkey, authority: &Pubkey) -> Instruction {
+    counter_instruction(program_id, counter, authority, CounterInstruction::Reset)
+}
+
+pub fn set_value(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    value: u64,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::SetValue { value },
+    )
+}
+
+// `None` renounces the authority for good
+pub fn set_authority(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    new_authority: Option<Pubkey>,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::SetAuthority { new_authority },
+    )
+}
+
+pub fn freeze(program_id: &Pubkey, counter: &Pubkey, authority: &Pubkey) -> Instruction {
+    counter_instruction(program_id, counter, authority, CounterInstruction::Freeze)
+}
+
+pub fn thaw(program_id: &Pubkey, counter: &Pubkey, authority: &Pubkey) -> Instruction {
+    counter_instruction(program_id, counter, authority, CounterInstruction::Thaw)
+}
+
+// Limit how often the counter may be incremented, `None` lifts the limit
+pub fn set_rate_limit(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    rate_limit: Option<RateLimit>,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::SetRateLimit { rate_limit },
+    )
+}
+
+// Charge `fee.price` lamports per increment, paid to `fee.treasury`. `None` makes
+// increments free again.
+pub fn set_fee(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    fee: Option<CounterFee>,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::SetFee { fee },
+    )
+}
+
+// Add the accounts an increment of a counter with a fee needs: the signer becomes
+// writable to pay, and the treasury and the system program are appended. Works for
+// `increment`, `increment_by`, `increment_shard`, `increment_shard_by` and `contribute`.
+pub fn with_fee(mut instruction: Instruction, treasury: &Pubkey) -> Instruction {
+    accounts::add_fee(&mut instruction.accounts, treasury);
+    instruction
+}
+
+// Only let signers holding at least `gate.min_balance` of `gate.mint` increment the
+// counter. `None` lifts the gate.
+pub fn set_token_gate(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    gate: Option<TokenGate>,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::SetTokenGate { gate },
+    )
+}
+
+// Add the token account an increment of a gated counter needs. It has to be owned by
+// the instruction's signer and hold enough of the gate's mint. Works for `increment`,
+// `increment_by`, `increment_shard`, `increment_shard_by` and `contribute`. Apply after
+// `with_fee`.
+pub fn with_token_account(mut instruction: Instruction, token_account: &Pubkey) -> Instruction {
+    accounts::add_token_account(&mut instruction.accounts, token_account);
+    instruction
+}
+
+// Open the counter to contributions from anyone, or back to its authority only
+pub fn set_shared(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    shared: bool,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::SetShared { shared },
+    )
+}
+
+// Register `signer` with the counter's multisig. Signers only stand in for the
+// authority once `set_threshold` sets how many of them have to sign.
+pub fn add_signer(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    signer: Pubkey,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::AddSigner { signer },
+    )
+}
+
+pub fn remove_signer(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    signer: Pubkey,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::RemoveSigner { signer },
+    )
+}
+
+// Require `threshold` of the counter's signers to sign from now on, 0 goes back to its
+// authority key
+pub fn set_threshold(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    threshold: u8,
+) -> Instruction {
+    counter_instruction(
+        program_id,
+        counter,
+        authority,
+        CounterInstruction::SetThreshold { threshold },
+    )
+}
+
+// Add more signers of a counter's multisig to an instruction. Its authority account has
+// to be one of them and is counted as well, so for a threshold of M pass one signer as
+// the authority and the M - 1 others here. Apply after `with_fee`.
+pub fn with_signers(mut instruction: Instruction, signers: &[Pubkey]) -> Instruction {
+    accounts::add_signers(&mut instruction.accounts, signers);
+    instruction
+}
+
+// Let `delegate` increment the counter at most `allowance` times, by any amount each,
+// until the unix time `expires_at`. `None` leaves either unlimited. `payer` funds the delegate's
+// record the first time, approving again replaces the grant.
+pub fn approve_delegate(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    delegate: &Pubkey,
+    allowance: Option<u64>,
+    expires_at: Option<i64>,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::ApproveDelegate {
+            delegate: *delegate,
+            allowance,
+            expires_at,
+        }
+        .pack(),
+        accounts::approve_delegate(
+            &delegate_address(program_id, counter, delegate),
+            counter,
+            payer,
+            authority,
+            &config_address(program_id),
+        ),
+    )
+}
+
+// End `delegate`'s grant and send the rent of its record to `destination`. Once the
+// counter is closed any signer can pass as `authority`, and `destination` has to be the
+// record's payer.
+pub fn revoke_delegate(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    delegate: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::RevokeDelegate.pack(),
+        accounts::revoke_delegate(
+            &delegate_address(program_id, counter, delegate),
+            counter,
+            authority,
+            destination,
+            &config_address(program_id),
+        ),
+    )
+}
+
+// Turn an increment built with a delegate as its authority into one the program accepts
+// from that delegate, by appending the delegate's record. Works for `increment`,
+// `increment_by`, `increment_shard` and `increment_shard_by`. Apply after `with_fee`.
+pub fn with_delegate(mut instruction: Instruction, counter: &Pubkey) -> Instruction {
+    let delegate = instruction.accounts[1].pubkey;
+    accounts::add_delegate_record(
+        &mut instruction.accounts,
+        &delegate_address(&instruction.program_id, counter, &delegate),
+    );
+    instruction
+}
+
+// Increment the counter by `amount` and credit it to `contributor`, who pays for their
+// contribution record the first time. Contributors may add any amount, as often as they
+// like, so totals weigh contributors by amount.
+pub fn contribute(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    contributor: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::Contribute { amount }.pack(),
+        accounts::contribute(
+            counter,
+            contributor,
+            &contribution_address(program_id, counter, contributor),
+            &config_address(program_id),
+        ),
+    )
+}
+
+// Close `contributor`'s contribution record to the counter and send its lamports to
+// `destination`
+pub fn close_contribution(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    contributor: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::CloseContribution.pack(),
+        accounts::close_contribution(
+            &contribution_address(program_id, counter, contributor),
+            contributor,
+            destination,
+        ),
+    )
+}
+
+// Close a counter and send its lamports to `destination`
+pub fn close_counter(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    close_sharded_counter(program_id, counter, authority, destination, 0)
+}
+
+// Close a counter along with its `shards` shards
+pub fn close_sharded_counter(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+    shards: u8,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::CloseCounter.pack(),
+        accounts::close_counter(
+            counter,
+            authority,
+            destination,
+            &config_address(program_id),
+            &shard_addresses(program_id, counter, shards),
+        ),
+    )
+}
+
+// Add shard number `shard` to a counter, shards have to be added in order
+pub fn initialize_shard(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    shard: u8,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::InitializeShard { shard }.pack(),
+        accounts::initialize_shard(
+            &shard_address(program_id, counter, shard),
+            counter,
+            payer,
+            authority,
+            &config_address(program_id),
+        ),
+    )
+}
+
+// Increment through one of the counter's shards, leaving the counter read only
+pub fn increment_shard(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    shard: u8,
+    authority: &Pubkey,
+) -> Instruction {
+    shard_instruction(
+        program_id,
+        counter,
+        shard,
+        authority,
+        CounterInstruction::IncrementCounter,
+    )
+}
+
+pub fn increment_shard_by(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    shard: u8,
+    authority: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    shard_instruction(
+        program_id,
+        counter,
+        shard,
+        authority,
+        CounterInstruction::IncrementBy { amount },
+    )
+}
+
+// Fold the pending increments of all `shards` shards into the counter
+pub fn aggregate(program_id: &Pubkey, counter: &Pubkey, shards: u8) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::Aggregate.pack(),
+        accounts::aggregate(
+            counter,
+            &config_address(program_id),
+            &shard_addresses(program_id, counter, shards),
+        ),
+    )
+}
+
+// Upgrade a versioned counter to the current layout, `payer` tops up the rent
+pub fn migrate_counter(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    migrate(program_id, counter, false, authority, false, payer)
+}
+
+// Upgrade a legacy counter, both the counter keypair and its new `authority` sign
+pub fn migrate_legacy_counter(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    migrate(program_id, counter, true, authority, true, payer)
+}
+
+// Read a counter, the count comes back as return data
+pub fn get_counter(program_id: &Pubkey, counter: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::GetCounter.pack(),
+        accounts::read_counter(counter),
+    )
+}
+
+// Read a counter's recent changes, they come back as return data for `decode_history`
+pub fn get_history(program_id: &Pubkey, counter: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::GetHistory.pack(),
+        accounts::read_counter(counter),
+    )
+}
+
+// Create the program config, `admin` has to be the program's upgrade authority and
+// pays for it
+pub fn initialize_config(program_id: &Pubkey, admin: &Pubkey, default_step: u64) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::InitializeConfig { default_step }.pack(),
+        accounts::initialize_config(
+            &config_address(program_id),
+            admin,
+            &find_program_data_address(program_id).0,
+        ),
+    )
+}
+
+pub fn set_paused(program_id: &Pubkey, admin: &Pubkey, paused: bool) -> Instruction {
+    config_instruction(program_id, admin, CounterInstruction::SetPaused { paused })
+}
+
+pub fn update_config(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    new_admin: Pubkey,
+    default_step: u64,
+) -> Instruction {
+    config_instruction(
+        program_id,
+        admin,
+        CounterInstruction::UpdateConfig {
+            new_admin,
+            default_step,
+        },
+    )
+}
+
+fn counter_instruction(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    authority: &Pubkey,
+    instruction: CounterInstruction,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &instruction.pack(),
+        accounts::update_counter(counter, authority, &config_address(program_id)),
+    )
+}
+
+fn shard_instruction(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    shard: u8,
+    authority: &Pubkey,
+    instruction: CounterInstruction,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &instruction.pack(),
+        accounts::update_shard(
+            &shard_address(program_id, counter, shard),
+            authority,
+            &config_address(program_id),
+            counter,
+        ),
+    )
+}
+
+fn shard_addresses(program_id: &Pubkey, counter: &Pubkey, shards: u8) -> Vec<Pubkey> {
+    (0..shards)
+        .map(|shard| shard_address(program_id, counter, shard))
+        .collect()
+}
+
+fn config_instruction(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    instruction: CounterInstruction,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &instruction.pack(),
+        accounts::update_config(&config_address(program_id), admin),
+    )
+}
+
+fn migrate(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    counter_signs: bool,
+    authority: &Pubkey,
+    authority_signs: bool,
+    payer: &Pubkey,
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &CounterInstruction::MigrateCounter.pack(),
+        accounts::migrate_counter(
+            counter,
+            counter_signs,
+            authority,
+            authority_signs,
+            payer,
+            &config_address(program_id),
+        ),
+    )
+}
+
+// Decode counter account data, rejecting anything that is not a current counter
+pub fn decode_counter(data: &[u8]) -> Result<CounterAccount, ProgramError> {
+    CounterAccount::unpack(data)
+}
+
+// Decode shard account data
+pub fn decode_shard(data: &[u8]) -> Result<ShardAccount, ProgramError> {
+    ShardAccount::unpack(data)
+}
+
+// Decode contribution account data
+pub fn decode_contribution(data: &[u8]) -> Result<ContributionAccount, ProgramError> {
+    ContributionAccount::unpack(data)
+}
+
+// The `limit` largest contributions, largest first. Ties are broken by contributor
+// address so the ranking does not depend on the order the records were fetched in.
+pub fn top_contributors(
+    mut contributions: Vec<(Pubkey, ContributionAccount)>,
+    limit: usize,
+) -> Vec<(Pubkey, ContributionAccount)> {
+    contributions.sort_by(|(_, a), (_, b)| {
+        b.total()
+            .cmp(&a.total())
+            .then_with(|| a.contributor().cmp(b.contributor()))
+    });
+    contributions.truncate(limit);
+    contributions
+}
+
+// Decode delegate account data
+pub fn decode_delegate(data: &[u8]) -> Result<DelegateAccount, ProgramError> {
+    DelegateAccount::unpack(data)
+}
+
+// Decode program config account data
+pub fn decode_config(data: &[u8]) -> Result<ProgramConfig, ProgramError> {
+    ProgramConfig::unpack(data)
+}
+
+#[cfg(feature = "client")]
+pub use rpc::*;
+
+#[cfg(feature = "client")]
+mod rpc {
+    use super::*;
+    use solana_account_decoder_client_types::UiAccountEncoding;
+    use solana_rpc_client::rpc_client::RpcClient;
+    use solana_rpc_client_api::{
+        config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        filter::{Memcmp, RpcFilterType},
+    };
+
+    // Offset of the authority in counter account data, after the discriminator, version
+    // and count
+    const AUTHORITY_OFFSET: usize = 8 + 1 + 8;
+
+    // Offset of the counter in contribution account data, after the discriminator and
+    // version, and of the counter generation after it
+    const CONTRIBUTION_COUNTER_OFFSET: usize = 8 + 1;
+    const CONTRIBUTION_GENERATION_OFFSET: usize = CONTRIBUTION_COUNTER_OFFSET + 32;
+
+    // Errors from fetching an account, either the RPC call or decoding its data failed.
+    // RPC errors are boxed, they are large and would bloat every Result otherwise
+    #[derive(Debug)]
+    pub enum FetchError {
+        Rpc(Box<solana_rpc_client_api::client_error::Error>),
+        Decode(ProgramError),
+    }
+
+    impl std::fmt::Display for FetchError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                FetchError::Rpc(err) => write!(f, "rpc error: {err}"),
+                FetchError::Decode(err) => write!(f, "invalid account data: {err}"),
+            }
+        }
+    }
+
+    impl std::error::Error for FetchError {}
+
+    impl From<solana_rpc_client_api::client_error::Error> for FetchError {
+        fn from(err: solana_rpc_client_api::client_error::Error) -> Self {
+            FetchError::Rpc(Box::new(err))
+        }
+    }
+
+    impl From<ProgramError> for FetchError {
+        fn from(err: ProgramError) -> Self {
+            FetchError::Decode(err)
+        }
+    }
+
+    pub fn fetch_counter(rpc: &RpcClient, counter: &Pubkey) -> Result<CounterAccount, FetchError> {
+        Ok(decode_counter(&rpc.get_account_data(counter)?)?)
+    }
+
+    pub fn fetch_delegate(
+        rpc: &RpcClient,
+        program_id: &Pubkey,
+        counter: &Pubkey,
+        delegate: &Pubkey,
+    ) -> Result<DelegateAccount, FetchError> {
+        Ok(decode_delegate(&rpc.get_account_data(
+            &delegate_address(program_id, counter, delegate),
+        )?)?)
+    }
+
+    pub fn fetch_config(rpc: &RpcClient, program_id: &Pubkey) -> Result<ProgramConfig, FetchError> {
+        Ok(decode_config(
+            &rpc.get_account_data(&config_address(program_id))?,
+        )?)
+    }
+
+    // All current counters owned by `authority`, using getProgramAccounts filters so
+    // only matching accounts are sent back
+    pub fn fetch_counters_by_authority(
+        rpc: &RpcClient,
+        program_id: &Pubkey,
+        authority: &Pubkey,
+    ) -> Result<Vec<(Pubkey, CounterAccount)>, FetchError> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize(CounterAccount::LEN as u64),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    0,
+                    &CounterAccount::DISCRIMINATOR,
+                )),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    AUTHORITY_OFFSET,
+                    authority.as_ref(),
+                )),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        rpc.get_program_accounts_with_config(program_id, config)?
+            .into_iter()
+            .map(|(address, account)| Ok((address, decode_counter(&account.data)?)))
+            .collect()
+    }
+    // Every contribution record of a counter, in no particular order. Records left from
+    // an earlier counter at the same address are skipped.
+    pub fn fetch_contributions(
+        rpc: &RpcClient,
+        program_id: &Pubkey,
+        counter: &Pubkey,
+    ) -> Result<Vec<(Pubkey, ContributionAccount)>, FetchError> {
+        let generation = fetch_counter(rpc, counter)?.generation();
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize(ContributionAccount::LEN as u64),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    0,
+                    &ContributionAccount::DISCRIMINATOR,
+                )),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    CONTRIBUTION_COUNTER_OFFSET,
+                    counter.as_ref(),
+                )),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    CONTRIBUTION_GENERATION_OFFSET,
+                    &generation.to_le_bytes(),
+                )),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        rpc.get_program_accounts_with_config(program_id, config)?
+            .into_iter()
+            .map(|(address, account)| Ok((address, decode_contribution(&account.data)?)))
+            .collect()
+    }
+
+    // The `limit` largest contributors to a counter, largest first
+    pub fn fetch_top_contributors(
+        rpc: &RpcClient,
+        program_id: &Pubkey,
+        counter: &Pubkey,
+        limit: usize,
+    ) -> Result<Vec<(Pubkey, ContributionAccount)>, FetchError> {
+        Ok(top_contributors(
+            fetch_contributions(rpc, program_id, counter)?,
+            limit,
+        ))
+    }
+}
@@ -0,0 +1,970 @@
+// Helpers for other programs to call the counter program through CPI.
+//
+// Every operation comes in two forms: `foo` signs with the signatures of the outer
+// transaction, `foo_signed` additionally signs for PDAs of the calling program. The
+// latter lets a program own counters on behalf of its users by making one of its
+// PDAs the counter authority.
+//
+// Every counter instruction returns the resulting count as return data, read it back
+// with `get_count_return` right after the call.
+
+use crate::{
+    accounts, CounterBounds, CounterFee, CounterInstruction, HistoryEntry, RateLimit, TokenGate,
+    HISTORY_LEN,
+};
+use borsh::BorshDeserialize;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+// Accounts needed to create a counter
+pub struct InitializeCounter<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+    pub payer: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    // The program-wide account handing out counter generations
+    pub generation: &'a AccountInfo<'info>,
+}
+
+impl<'info> InitializeCounter<'_, 'info> {
+    fn invoke(&self, instruction: CounterInstruction, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        let accounts = accounts::initialize_counter(
+            self.counter.key,
+            self.payer.key,
+            self.authority.key,
+            self.config.key,
+            self.generation.key,
+        );
+        invoke_signed(
+            &Instruction::new_with_bytes(*self.counter_program.key, &instruction.pack(), accounts),
+            &[
+                self.counter.clone(),
+                self.payer.clone(),
+                self.system_program.clone(),
+                self.authority.clone(),
+                self.config.clone(),
+                self.generation.clone(),
+            ],
+            signer_seeds,
+        )
+    }
+}
+
+// Accounts needed to change an existing counter
+pub struct UpdateCounter<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    // The other signers of a multisig counter, empty for any other counter
+    pub signers: &'a [AccountInfo<'info>],
+    // The record of its grant, when the authority is a delegate of the counter
+    pub delegate_record: Option<&'a AccountInfo<'info>>,
+    // A token account of the authority, for increments of a token gated counter
+    pub token_account: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'info> UpdateCounter<'_, 'info> {
+    fn invoke(&self, instruction: CounterInstruction, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        self.invoke_with_fee(instruction, None, signer_seeds)
+    }
+
+    fn invoke_with_fee(
+        &self,
+        instruction: CounterInstruction,
+        fee: Option<&PayFee<'_, 'info>>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let mut accounts =
+            accounts::update_counter(self.counter.key, self.authority.key, self.config.key);
+        let mut account_infos = vec![
+            self.counter.clone(),
+            self.authority.clone(),
+            self.config.clone(),
+        ];
+        if let Some(fee) = fee {
+            fee.add_to(&mut accounts, &mut account_infos);
+        }
+        add_delegate_record(&mut accounts, &mut account_infos, self.delegate_record);
+        add_token_account(&mut accounts, &mut account_infos, self.token_account);
+        invoke_counter(
+            self.counter_program,
+            instruction,
+            accounts,
+            account_infos,
+            self.signers,
+            signer_seeds,
+        )
+    }
+}
+
+// Accounts needed to pay the fee of a counter whose increments cost a fee. The signer
+// pays, the authority of `UpdateCounter` and `UpdateShard` or the contributor of
+// `Contribute`, so it has to be writable.
+pub struct PayFee<'a, 'info> {
+    pub treasury: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'info> PayFee<'_, 'info> {
+    fn add_to(&self, accounts: &mut Vec<AccountMeta>, account_infos: &mut Vec<AccountInfo<'info>>) {
+        accounts::add_fee(accounts, self.treasury.key);
+        account_infos.extend([self.treasury.clone(), self.system_program.clone()]);
+    }
+}
+
+// Accounts needed to increment a sharded counter through one of its shards
+pub struct UpdateShard<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub shard: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+    // The other signers of a multisig counter, empty for any other counter
+    pub signers: &'a [AccountInfo<'info>],
+    // The record of its grant, when the authority is a delegate of the counter
+    pub delegate_record: Option<&'a AccountInfo<'info>>,
+    // A token account of the authority, for increments of a token gated counter
+    pub token_account: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'info> UpdateShard<'_, 'info> {
+    fn invoke(&self, instruction: CounterInstruction, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        self.invoke_with_fee(instruction, None, signer_seeds)
+    }
+
+    fn invoke_with_fee(
+        &self,
+        instruction: CounterInstruction,
+        fee: Option<&PayFee<'_, 'info>>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let mut accounts = accounts::update_shard(
+            self.shard.key,
+            self.authority.key,
+            self.config.key,
+            self.counter.key,
+        );
+        let mut account_infos = vec![
+            self.shard.clone(),
+            self.authority.clone(),
+            self.config.clone(),
+            self.counter.clone(),
+        ];
+        if let Some(fee) = fee {
+            fee.add_to(&mut accounts, &mut account_infos);
+        }
+        add_delegate_record(&mut accounts, &mut account_infos, self.delegate_record);
+        add_token_account(&mut accounts, &mut account_infos, self.token_account);
+        invoke_counter(
+            self.counter_program,
+            instruction,
+            accounts,
+            account_infos,
+            self.signers,
+            signer_seeds,
+        )
+    }
+}
+
+// Accounts needed to contribute to a counter. The contributor pays for their
+// contribution record on their first contribution.
+pub struct Contribute<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+    pub contributor: &'a AccountInfo<'info>,
+    pub contribution: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    // The other signers of a multisig counter, empty for any other counter
+    pub signers: &'a [AccountInfo<'info>],
+    // A token account of the contributor, for contributions to a token gated counter
+    pub token_account: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'info> Contribute<'_, 'info> {
+    fn invoke(
+        &self,
+        amount: u64,
+        fee: Option<&PayFee<'_, 'info>>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let mut accounts = accounts::contribute(
+            self.counter.key,
+            self.contributor.key,
+            self.contribution.key,
+            self.config.key,
+        );
+        let mut account_infos = vec![
+            self.counter.clone(),
+            self.contributor.clone(),
+            self.contribution.clone(),
+            self.system_program.clone(),
+            self.config.clone(),
+        ];
+        if let Some(fee) = fee {
+            fee.add_to(&mut accounts, &mut account_infos);
+        }
+        add_token_account(&mut accounts, &mut account_infos, self.token_account);
+        invoke_counter(
+            self.counter_program,
+            CounterInstruction::Contribute { amount },
+            accounts,
+            account_infos,
+            self.signers,
+            signer_seeds,
+        )
+    }
+}
+
+// Accounts needed to close a contribution record, its rent goes to the destination
+pub struct CloseContribution<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub contribution: &'a AccountInfo<'info>,
+    pub contributor: &'a AccountInfo<'info>,
+    pub destination: &'a AccountInfo<'info>,
+}
+
+impl<'info> CloseContribution<'_, 'info> {
+    fn invoke(&self, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        let accounts = accounts::close_contribution(
+            self.contribution.key,
+            self.contributor.key,
+            self.destination.key,
+        );
+        invoke_signed(
+            &Instruction::new_with_bytes(
+                *self.counter_program.key,
+                &CounterInstruction::CloseContribution.pack(),
+                accounts,
+            ),
+            &[
+                self.contribution.clone(),
+                self.contributor.clone(),
+                self.destination.clone(),
+            ],
+            signer_seeds,
+        )
+    }
+}
+
+// Accounts needed to approve a delegate of a counter. The payer funds the delegate's
+// record the first time it is approved.
+pub struct ApproveDelegate<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub delegate_record: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+    pub payer: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    // The other signers of a multisig counter, empty for any other counter
+    pub signers: &'a [AccountInfo<'info>],
+}
+
+impl<'info> ApproveDelegate<'_, 'info> {
+    fn invoke(&self, instruction: CounterInstruction, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        let accounts = accounts::approve_delegate(
+            self.delegate_record.key,
+            self.counter.key,
+            self.payer.key,
+            self.authority.key,
+            self.config.key,
+        );
+        invoke_counter(
+            self.counter_program,
+            instruction,
+            accounts,
+            vec![
+                self.delegate_record.clone(),
+                self.counter.clone(),
+                self.payer.clone(),
+                self.system_program.clone(),
+                self.authority.clone(),
+                self.config.clone(),
+            ],
+            self.signers,
+            signer_seeds,
+        )
+    }
+}
+
+// Accounts needed to revoke a delegate of a counter, the rent of its record goes to the
+// destination
+pub struct RevokeDelegate<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub delegate_record: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub destination: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    // The other signers of a multisig counter, empty for any other counter
+    pub signers: &'a [AccountInfo<'info>],
+}
+
+impl<'info> RevokeDelegate<'_, 'info> {
+    fn invoke(&self, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        let accounts = accounts::revoke_delegate(
+            self.delegate_record.key,
+            self.counter.key,
+            self.authority.key,
+            self.destination.key,
+            self.config.key,
+        );
+        invoke_counter(
+            self.counter_program,
+            CounterInstruction::RevokeDelegate,
+            accounts,
+            vec![
+                self.delegate_record.clone(),
+                self.counter.clone(),
+                self.authority.clone(),
+                self.destination.clone(),
+                self.config.clone(),
+            ],
+            self.signers,
+            signer_seeds,
+        )
+    }
+}
+
+// Accounts needed to close a counter
+pub struct CloseCounter<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub destination: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    // All shards of a sharded counter in order, they are closed along with it
+    pub shards: &'a [AccountInfo<'info>],
+    // The other signers of a multisig counter, empty for any other counter
+    pub signers: &'a [AccountInfo<'info>],
+}
+
+impl<'info> CloseCounter<'_, 'info> {
+    fn invoke(&self, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        let shards: Vec<Pubkey> = self.shards.iter().map(|shard| *shard.key).collect();
+        let accounts = accounts::close_counter(
+            self.counter.key,
+            self.authority.key,
+            self.destination.key,
+            self.config.key,
+            &shards,
+        );
+        let mut account_infos = vec![
+            self.counter.clone(),
+            self.authority.clone(),
+            self.destination.clone(),
+            self.config.clone(),
+        ];
+        account_infos.extend_from_slice(self.shards);
+        invoke_counter(
+            self.counter_program,
+            CounterInstruction::CloseCounter,
+            accounts,
+            account_infos,
+            self.signers,
+            signer_seeds,
+        )
+    }
+}
+
+// Accounts needed to add the next shard to a counter. The payer funds the shard.
+pub struct InitializeShard<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub shard: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+    pub payer: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    // The other signers of a multisig counter, empty for any other counter
+    pub signers: &'a [AccountInfo<'info>],
+}
+
+impl<'info> InitializeShard<'_, 'info> {
+    fn invoke(&self, shard: u8, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        let accounts = accounts::initialize_shard(
+            self.shard.key,
+            self.counter.key,
+            self.payer.key,
+            self.authority.key,
+            self.config.key,
+        );
+        invoke_counter(
+            self.counter_program,
+            CounterInstruction::InitializeShard { shard },
+            accounts,
+            vec![
+                self.shard.clone(),
+                self.counter.clone(),
+                self.payer.clone(),
+                self.system_program.clone(),
+                self.authority.clone(),
+                self.config.clone(),
+            ],
+            self.signers,
+            signer_seeds,
+        )
+    }
+}
+
+// Accounts needed to fold the pending increments of a counter's shards into it. Anyone
+// may aggregate, so there are no signers.
+pub struct Aggregate<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    pub shards: &'a [AccountInfo<'info>],
+}
+
+impl Aggregate<'_, '_> {
+    fn invoke(&self, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+        let shards: Vec<Pubkey> = self.shards.iter().map(|shard| *shard.key).collect();
+        let mut account_infos = vec![self.counter.clone(), self.config.clone()];
+        account_infos.extend_from_slice(self.shards);
+        invoke_signed(
+            &Instruction::new_with_bytes(
+                *self.counter_program.key,
+                &CounterInstruction::Aggregate.pack(),
+                accounts::aggregate(self.counter.key, self.config.key, &shards),
+            ),
+            &account_infos,
+            signer_seeds,
+        )
+    }
+}
+
+// Invoke the counter program, with the other signers of a multisig counter after the
+// instruction's own accounts
+fn invoke_counter<'info>(
+    counter_program: &AccountInfo<'info>,
+    instruction: CounterInstruction,
+    mut accounts: Vec<AccountMeta>,
+    mut account_infos: Vec<AccountInfo<'info>>,
+    signers: &[AccountInfo<'info>],
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts::add_signers(&mut accounts, signers.iter().map(|signer| signer.key));
+    account_infos.extend_from_slice(signers);
+    invoke_signed(
+        &Instruction::new_with_bytes(*counter_program.key, &instruction.pack(), accounts),
+        &account_infos,
+        signer_seeds,
+    )
+}
+
+// Add the delegate's record, when the caller increments as a delegate
+fn add_delegate_record<'info>(
+    accounts: &mut Vec<AccountMeta>,
+    account_infos: &mut Vec<AccountInfo<'info>>,
+    delegate_record: Option<&AccountInfo<'info>>,
+) {
+    if let Some(delegate_record) = delegate_record {
+        accounts::add_delegate_record(accounts, delegate_record.key);
+        account_infos.push(delegate_record.clone());
+    }
+}
+
+// Add the signer's token account, when the caller passed one for a token gated counter
+fn add_token_account<'info>(
+    accounts: &mut Vec<AccountMeta>,
+    account_infos: &mut Vec<AccountInfo<'info>>,
+    token_account: Option<&AccountInfo<'info>>,
+) {
+    if let Some(token_account) = token_account {
+        accounts::add_token_account(accounts, token_account.key);
+        account_infos.push(token_account.clone());
+    }
+}
+
+// Accounts needed to read a counter
+pub struct GetCounter<'a, 'info> {
+    pub counter_program: &'a AccountInfo<'info>,
+    pub counter: &'a AccountInfo<'info>,
+}
+
+// Count returned by the counter program's last instruction in this transaction
+pub fn get_count_return(counter_program_id: &Pubkey) -> Option<u64> {
+    let (program_id, data) = get_return_data()?;
+    if &program_id != counter_program_id {
+        return None;
+    }
+    decode_count(&data)
+}
+
+// Decode a count from counter program return data. The runtime strips trailing zero
+// bytes from the return data reported to clients, so shorter input is zero padded.
+pub fn decode_count(return_data: &[u8]) -> Option<u64> {
+    if return_data.len() > 8 {
+        return None;
+    }
+    let mut bytes = [0u8; 8];
+    bytes[..return_data.len()].copy_from_slice(return_data);
+    Some(u64::from_le_bytes(bytes))
+}
+
+// Decode a counter's history from GetHistory return data, oldest change first. Trailing
+// zero bytes may have been stripped like for the count.
+pub fn decode_history(return_data: &[u8]) -> Option<Vec<HistoryEntry>> {
+    let max_len = 4 + HISTORY_LEN * HistoryEntry::LEN;
+    if return_data.len() > max_len {
+        return None;
+    }
+    let mut padded = return_data.to_vec();
+    padded.resize(max_len, 0);
+    let rest = &mut &padded[..];
+    let history = Vec::<HistoryEntry>::deserialize(rest).ok()?;
+
+    // Everything after the entries has to be padding
+    if max_len - rest.len() < return_data.len() || history.len() > HISTORY_LEN {
+        return None;
+    }
+    Some(history)
+}
+
+// Read a counter's value through CPI
+pub fn get_counter(accounts: &GetCounter) -> Result<u64, ProgramError> {
+    invoke(
+        &Instruction::new_with_bytes(
+            *accounts.counter_program.key,
+            &CounterInstruction::GetCounter.pack(),
+            accounts::read_counter(accounts.counter.key),
+        ),
+        std::slice::from_ref(accounts.counter),
+    )?;
+    get_count_return(accounts.counter_program.key).ok_or(ProgramError::InvalidAccountData)
+}
+
+// Read a counter's recent changes through CPI, oldest first
+pub fn get_history(accounts: &GetCounter) -> Result<Vec<HistoryEntry>, ProgramError> {
+    invoke(
+        &Instruction::new_with_bytes(
+            *accounts.counter_program.key,
+            &CounterInstruction::GetHistory.pack(),
+            accounts::read_counter(accounts.counter.key),
+        ),
+        std::slice::from_ref(accounts.counter),
+    )?;
+    get_return_data()
+        .filter(|(program_id, _)| program_id == accounts.counter_program.key)
+        .and_then(|(_, data)| decode_history(&data))
+        .ok_or(ProgramError::InvalidAccountData)
+}
+
+pub fn initialize_counter(
+    accounts: &InitializeCounter,
+    initial_value: u64,
+    index: u64,
+    bounds: Option<CounterBounds>,
+) -> ProgramResult {
+    initialize_counter_signed(accounts, initial_value, index, bounds, &[])
+}
+
+pub fn initialize_counter_signed(
+    accounts: &InitializeCounter,
+    initial_value: u64,
+    index: u64,
+    bounds: Option<CounterBounds>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let instruction = CounterInstruction::InitializeCounter {
+        initial_value,
+        index,
+        bounds,
+    };
+    accounts.invoke(instruction, signer_seeds)
+}
+
+pub fn increment(accounts: &UpdateCounter) -> ProgramResult {
+    increment_signed(accounts, &[])
+}
+
+pub fn increment_signed(accounts: &UpdateCounter, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    accounts.invoke(CounterInstruction::IncrementCounter, signer_seeds)
+}
+
+pub fn increment_by(accounts: &UpdateCounter, amount: u64) -> ProgramResult {
+    increment_by_signed(accounts, amount, &[])
+}
+
+pub fn increment_by_signed(
+    accounts: &UpdateCounter,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::IncrementBy { amount }, signer_seeds)
+}
+
+pub fn increment_with_fee<'info>(
+    accounts: &UpdateCounter<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+) -> ProgramResult {
+    increment_with_fee_signed(accounts, fee, &[])
+}
+
+pub fn increment_with_fee_signed<'info>(
+    accounts: &UpdateCounter<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke_with_fee(
+        CounterInstruction::IncrementCounter,
+        Some(fee),
+        signer_seeds,
+    )
+}
+
+pub fn increment_by_with_fee<'info>(
+    accounts: &UpdateCounter<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+    amount: u64,
+) -> ProgramResult {
+    increment_by_with_fee_signed(accounts, fee, amount, &[])
+}
+
+pub fn increment_by_with_fee_signed<'info>(
+    accounts: &UpdateCounter<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke_with_fee(
+        CounterInstruction::IncrementBy { amount },
+        Some(fee),
+        signer_seeds,
+    )
+}
+
+// Shard increments return the count with only this shard's pending increments folded
+// in, the other shards' are left out
+pub fn increment_shard(accounts: &UpdateShard) -> ProgramResult {
+    increment_shard_signed(accounts, &[])
+}
+
+pub fn increment_shard_signed(accounts: &UpdateShard, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    accounts.invoke(CounterInstruction::IncrementCounter, signer_seeds)
+}
+
+pub fn increment_shard_by(accounts: &UpdateShard, amount: u64) -> ProgramResult {
+    increment_shard_by_signed(accounts, amount, &[])
+}
+
+pub fn increment_shard_by_signed(
+    accounts: &UpdateShard,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::IncrementBy { amount }, signer_seeds)
+}
+
+pub fn increment_shard_with_fee<'info>(
+    accounts: &UpdateShard<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+) -> ProgramResult {
+    increment_shard_with_fee_signed(accounts, fee, &[])
+}
+
+pub fn increment_shard_with_fee_signed<'info>(
+    accounts: &UpdateShard<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke_with_fee(
+        CounterInstruction::IncrementCounter,
+        Some(fee),
+        signer_seeds,
+    )
+}
+
+pub fn increment_shard_by_with_fee<'info>(
+    accounts: &UpdateShard<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+    amount: u64,
+) -> ProgramResult {
+    increment_shard_by_with_fee_signed(accounts, fee, amount, &[])
+}
+
+pub fn increment_shard_by_with_fee_signed<'info>(
+    accounts: &UpdateShard<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke_with_fee(
+        CounterInstruction::IncrementBy { amount },
+        Some(fee),
+        signer_seeds,
+    )
+}
+
+pub fn decrement(accounts: &UpdateCounter) -> ProgramResult {
+    decrement_signed(accounts, &[])
+}
+
+pub fn decrement_signed(accounts: &UpdateCounter, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    accounts.invoke(CounterInstruction::Decrement, signer_seeds)
+}
+
+pub fn decrement_by(accounts: &UpdateCounter, amount: u64) -> ProgramResult {
+    decrement_by_signed(accounts, amount, &[])
+}
+
+pub fn decrement_by_signed(
+    accounts: &UpdateCounter,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::DecrementBy { amount }, signer_seeds)
+}
+
+pub fn reset(accounts: &UpdateCounter) -> ProgramResult {
+    reset_signed(accounts, &[])
+}
+
+pub fn reset_signed(accounts: &UpdateCounter, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    accounts.invoke(CounterInstruction::Reset, signer_seeds)
+}
+
+pub fn set_value(accounts: &UpdateCounter, value: u64) -> ProgramResult {
+    set_value_signed(accounts, value, &[])
+}
+
+pub fn set_value_signed(
+    accounts: &UpdateCounter,
+    value: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::SetValue { value }, signer_seeds)
+}
+
+pub fn set_authority(accounts: &UpdateCounter, new_authority: Option<Pubkey>) -> ProgramResult {
+    set_authority_signed(accounts, new_authority, &[])
+}
+
+pub fn set_authority_signed(
+    accounts: &UpdateCounter,
+    new_authority: Option<Pubkey>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(
+        CounterInstruction::SetAuthority { new_authority },
+        signer_seeds,
+    )
+}
+
+pub fn freeze(accounts: &UpdateCounter) -> ProgramResult {
+    freeze_signed(accounts, &[])
+}
+
+pub fn freeze_signed(accounts: &UpdateCounter, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    accounts.invoke(CounterInstruction::Freeze, signer_seeds)
+}
+
+pub fn thaw(accounts: &UpdateCounter) -> ProgramResult {
+    thaw_signed(accounts, &[])
+}
+
+pub fn thaw_signed(accounts: &UpdateCounter, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    accounts.invoke(CounterInstruction::Thaw, signer_seeds)
+}
+
+pub fn set_rate_limit(accounts: &UpdateCounter, rate_limit: Option<RateLimit>) -> ProgramResult {
+    set_rate_limit_signed(accounts, rate_limit, &[])
+}
+
+pub fn set_rate_limit_signed(
+    accounts: &UpdateCounter,
+    rate_limit: Option<RateLimit>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(
+        CounterInstruction::SetRateLimit { rate_limit },
+        signer_seeds,
+    )
+}
+
+pub fn set_fee(accounts: &UpdateCounter, fee: Option<CounterFee>) -> ProgramResult {
+    set_fee_signed(accounts, fee, &[])
+}
+
+pub fn set_fee_signed(
+    accounts: &UpdateCounter,
+    fee: Option<CounterFee>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::SetFee { fee }, signer_seeds)
+}
+
+pub fn set_token_gate(accounts: &UpdateCounter, gate: Option<TokenGate>) -> ProgramResult {
+    set_token_gate_signed(accounts, gate, &[])
+}
+
+pub fn set_token_gate_signed(
+    accounts: &UpdateCounter,
+    gate: Option<TokenGate>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::SetTokenGate { gate }, signer_seeds)
+}
+
+pub fn set_shared(accounts: &UpdateCounter, shared: bool) -> ProgramResult {
+    set_shared_signed(accounts, shared, &[])
+}
+
+pub fn set_shared_signed(
+    accounts: &UpdateCounter,
+    shared: bool,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::SetShared { shared }, signer_seeds)
+}
+
+pub fn add_signer(accounts: &UpdateCounter, signer: Pubkey) -> ProgramResult {
+    add_signer_signed(accounts, signer, &[])
+}
+
+pub fn add_signer_signed(
+    accounts: &UpdateCounter,
+    signer: Pubkey,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::AddSigner { signer }, signer_seeds)
+}
+
+pub fn remove_signer(accounts: &UpdateCounter, signer: Pubkey) -> ProgramResult {
+    remove_signer_signed(accounts, signer, &[])
+}
+
+pub fn remove_signer_signed(
+    accounts: &UpdateCounter,
+    signer: Pubkey,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::RemoveSigner { signer }, signer_seeds)
+}
+
+pub fn set_threshold(accounts: &UpdateCounter, threshold: u8) -> ProgramResult {
+    set_threshold_signed(accounts, threshold, &[])
+}
+
+pub fn set_threshold_signed(
+    accounts: &UpdateCounter,
+    threshold: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(CounterInstruction::SetThreshold { threshold }, signer_seeds)
+}
+
+pub fn contribute(accounts: &Contribute, amount: u64) -> ProgramResult {
+    contribute_signed(accounts, amount, &[])
+}
+
+pub fn contribute_signed(
+    accounts: &Contribute,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(amount, None, signer_seeds)
+}
+
+pub fn contribute_with_fee<'info>(
+    accounts: &Contribute<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+    amount: u64,
+) -> ProgramResult {
+    contribute_with_fee_signed(accounts, fee, amount, &[])
+}
+
+pub fn contribute_with_fee_signed<'info>(
+    accounts: &Contribute<'_, 'info>,
+    fee: &PayFee<'_, 'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(amount, Some(fee), signer_seeds)
+}
+
+pub fn close_contribution(accounts: &CloseContribution) -> ProgramResult {
+    close_contribution_signed(accounts, &[])
+}
+
+pub fn close_contribution_signed(
+    accounts: &CloseContribution,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(signer_seeds)
+}
+
+pub fn approve_delegate(
+    accounts: &ApproveDelegate,
+    delegate: Pubkey,
+    allowance: Option<u64>,
+    expires_at: Option<i64>,
+) -> ProgramResult {
+    approve_delegate_signed(accounts, delegate, allowance, expires_at, &[])
+}
+
+pub fn approve_delegate_signed(
+    accounts: &ApproveDelegate,
+    delegate: Pubkey,
+    allowance: Option<u64>,
+    expires_at: Option<i64>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(
+        CounterInstruction::ApproveDelegate {
+            delegate,
+            allowance,
+            expires_at,
+        },
+        signer_seeds,
+    )
+}
+
+pub fn revoke_delegate(accounts: &RevokeDelegate) -> ProgramResult {
+    revoke_delegate_signed(accounts, &[])
+}
+
+pub fn revoke_delegate_signed(
+    accounts: &RevokeDelegate,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(signer_seeds)
+}
+
+pub fn close_counter(accounts: &CloseCounter) -> ProgramResult {
+    close_counter_signed(accounts, &[])
+}
+
+pub fn close_counter_signed(accounts: &CloseCounter, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    accounts.invoke(signer_seeds)
+}
+
+pub fn initialize_shard(accounts: &InitializeShard, shard: u8) -> ProgramResult {
+    initialize_shard_signed(accounts, shard, &[])
+}
+
+pub fn initialize_shard_signed(
+    accounts: &InitializeShard,
+    shard: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    accounts.invoke(shard, signer_seeds)
+}
+
+pub fn aggregate(accounts: &Aggregate) -> ProgramResult {
+    aggregate_signed(accounts, &[])
+}
+
+pub fn aggregate_signed(accounts: &Aggregate, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    accounts.invoke(signer_seeds)
+}
@@ -1,18 +1,23 @@
+mod accounts;
+pub mod client;
+pub mod cpi;
+
 use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
     entrypoint::ProgramResult,
     msg,
-    program::invoke,
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     pubkey::Pubkey,
     system_instruction,
-    sysvar::{rent::Rent, Sysvar},
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
-// Program entrypoint
-entrypoint!(process_instruction);
+// Program entrypoint, left out when the crate is used as a library by other programs
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 
 // Function to route instructions to the correct handler
 pub fn process_instruction(
@@ -23,67 +28,641 @@ pub fn process_instruction(
     // Unpack instruction data
     let instruction = CounterInstruction::unpack(instruction_data)?;
 
+    // Counter changes are refused while the program is paused
+    let default_step = match instruction.config_position() {
+        Some(position) => {
+            let config = load_config(program_id, accounts.get(position))?;
+            if config.paused {
+                return Err(CounterError::Paused.into());
+            }
+            config.default_step
+        }
+        None => 1,
+    };
+
     // Match instruction type
     match instruction {
-        CounterInstruction::InitializeCounter { initial_value } => {
-            process_initialize_counter(program_id, accounts, initial_value)?
+        CounterInstruction::InitializeCounter {
+            initial_value,
+            index,
+            bounds,
+        } => process_initialize_counter(program_id, accounts, initial_value, index, bounds)?,
+        CounterInstruction::IncrementCounter => {
+            process_increment_counter(program_id, accounts, default_step)?
+        }
+        CounterInstruction::SetAuthority { new_authority } => {
+            process_set_authority(program_id, accounts, new_authority)?
+        }
+        CounterInstruction::Decrement => {
+            process_decrement_counter(program_id, accounts, default_step)?
+        }
+        CounterInstruction::IncrementBy { amount } => {
+            process_increment_counter(program_id, accounts, amount)?
+        }
+        CounterInstruction::DecrementBy { amount } => {
+            process_decrement_counter(program_id, accounts, amount)?
+        }
+        CounterInstruction::Reset => process_reset_counter(program_id, accounts)?,
+        CounterInstruction::SetValue { value } => process_set_value(program_id, accounts, value)?,
+        CounterInstruction::CloseCounter => process_close_counter(program_id, accounts)?,
+        CounterInstruction::MigrateCounter => process_migrate_counter(program_id, accounts)?,
+        CounterInstruction::Freeze => process_set_frozen(program_id, accounts, true)?,
+        CounterInstruction::Thaw => process_set_frozen(program_id, accounts, false)?,
+        CounterInstruction::InitializeConfig { default_step } => {
+            process_initialize_config(program_id, accounts, default_step)?
+        }
+        CounterInstruction::SetPaused { paused } => {
+            process_set_paused(program_id, accounts, paused)?
+        }
+        CounterInstruction::UpdateConfig {
+            new_admin,
+            default_step,
+        } => process_update_config(program_id, accounts, new_admin, default_step)?,
+        CounterInstruction::GetCounter => process_get_counter(program_id, accounts)?,
+        CounterInstruction::InitializeShard { shard } => {
+            process_initialize_shard(program_id, accounts, shard)?
+        }
+        CounterInstruction::Aggregate => process_aggregate(program_id, accounts)?,
+        CounterInstruction::GetHistory => process_get_history(program_id, accounts)?,
+        CounterInstruction::SetRateLimit { rate_limit } => {
+            process_set_rate_limit(program_id, accounts, rate_limit)?
+        }
+        CounterInstruction::SetShared { shared } => {
+            process_set_shared(program_id, accounts, shared)?
+        }
+        CounterInstruction::Contribute { amount } => {
+            process_contribute(program_id, accounts, amount)?
+        }
+        CounterInstruction::SetFee { fee } => process_set_fee(program_id, accounts, fee)?,
+        CounterInstruction::AddSigner { signer } => {
+            process_add_signer(program_id, accounts, signer)?
         }
-        CounterInstruction::IncrementCounter => process_increment_counter(program_id, accounts)?,
+        CounterInstruction::RemoveSigner { signer } => {
+            process_remove_signer(program_id, accounts, signer)?
+        }
+        CounterInstruction::SetThreshold { threshold } => {
+            process_set_threshold(program_id, accounts, threshold)?
+        }
+        CounterInstruction::ApproveDelegate {
+            delegate,
+            allowance,
+            expires_at,
+        } => process_approve_delegate(program_id, accounts, delegate, allowance, expires_at)?,
+        CounterInstruction::RevokeDelegate => process_revoke_delegate(program_id, accounts)?,
+        CounterInstruction::SetTokenGate { gate } => {
+            process_set_token_gate(program_id, accounts, gate)?
+        }
+        CounterInstruction::CloseContribution => process_close_contribution(program_id, accounts)?,
     };
     Ok(())
 }
 
-// Instructions that our program can execute
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+// Instructions that our program can execute.
+//
+// The wire format is the Borsh encoding of this enum: a one byte variant number followed
+// by the variant's fields in declaration order, with no padding and nothing after them.
+//
+//   u64             8 bytes, little endian
+//   i64             8 bytes, little endian, two's complement
+//   bool            1 byte, 0 or 1
+//   Pubkey          32 bytes
+//   Option<T>       1 byte, 0 for None or 1 followed by T
+//   CounterBounds   min u64, max u64, policy as 1 byte (0 error, 1 saturate, 2 wrap)
+//   RateLimit       max_increments u64, window u64, unit as 1 byte (0 slots, 1 seconds)
+//   CounterFee      price u64, treasury Pubkey
+//   TokenGate       mint Pubkey, min_balance u64
+//
+// For example IncrementBy { amount: 5 } is [4, 5, 0, 0, 0, 0, 0, 0, 0] and
+// InitializeCounter without bounds is [0] + initial_value + index + [0].
+//
+// Variant numbers and the layout of existing variants never change, instructions that
+// need different data are added as new variants at the end.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
 pub enum CounterInstruction {
-    InitializeCounter { initial_value: u64 }, // variant 0
-    IncrementCounter,                         // variant 1
+    // variant 0: `None` bounds leave the counter unbounded, failing on u64 overflow
+    InitializeCounter {
+        initial_value: u64,
+        index: u64,
+        bounds: Option<CounterBounds>,
+    },
+    // variant 1: adds the program's default step, to the counter or to one of its shards
+    IncrementCounter,
+    // variant 2: `None` renounces the authority for good
+    SetAuthority {
+        new_authority: Option<Pubkey>,
+    },
+    // variant 3: subtracts the program's default step
+    Decrement,
+    // variant 4: like IncrementCounter, the counter or one of its shards
+    IncrementBy {
+        amount: u64,
+    },
+    // variant 5
+    DecrementBy {
+        amount: u64,
+    },
+    // variant 6: back to the counter's minimum
+    Reset,
+    // variant 7
+    SetValue {
+        value: u64,
+    },
+    // variant 8
+    CloseCounter,
+    // variant 9
+    MigrateCounter,
+    // variant 10
+    Freeze,
+    // variant 11
+    Thaw,
+    // variant 12: only the program's upgrade authority may create the config, and it
+    // becomes its admin
+    InitializeConfig {
+        default_step: u64,
+    },
+    // variant 13
+    SetPaused {
+        paused: bool,
+    },
+    // variant 14
+    UpdateConfig {
+        new_admin: Pubkey,
+        default_step: u64,
+    },
+    // variant 15: read only, returns the count as return data
+    GetCounter,
+    // variant 16: adds the counter's next shard, shards are numbered from 0
+    InitializeShard {
+        shard: u8,
+    },
+    // variant 17: folds the pending increments of the given shards into the counter
+    Aggregate,
+    // variant 18: read only, returns the counter's recent changes as return data
+    GetHistory,
+    // variant 19: `None` lifts the limit. Sharded counters cannot be rate limited.
+    SetRateLimit {
+        rate_limit: Option<RateLimit>,
+    },
+    // variant 20: shared counters take contributions from anyone, not just the authority
+    SetShared {
+        shared: bool,
+    },
+    // variant 21: increments the counter and adds `amount` to the signer's contribution.
+    // Contributions are weighted by amount, a contributor may add any amount any number
+    // of times, so they are no one-person-one-vote tally.
+    Contribute {
+        amount: u64,
+    },
+    // variant 22: `None` makes increments free again
+    SetFee {
+        fee: Option<CounterFee>,
+    },
+    // variant 23: registers one more signer of the counter's multisig
+    AddSigner {
+        signer: Pubkey,
+    },
+    // variant 24
+    RemoveSigner {
+        signer: Pubkey,
+    },
+    // variant 25: how many of the registered signers have to sign, 0 goes back to the
+    // single authority key
+    SetThreshold {
+        threshold: u8,
+    },
+    // variant 26: lets `delegate` increment the counter at most `allowance` times, by
+    // any amount each, until the unix time `expires_at`. `None` leaves either unlimited,
+    // approving the same delegate again replaces its grant.
+    ApproveDelegate {
+        delegate: Pubkey,
+        allowance: Option<u64>,
+        expires_at: Option<i64>,
+    },
+    // variant 27: closes a delegate's record, ending its grant
+    RevokeDelegate,
+    // variant 28: only holders of the gate's token may increment, `None` lets anyone
+    // allowed to increment do so again
+    SetTokenGate {
+        gate: Option<TokenGate>,
+    },
+    // variant 29: closes the signer's contribution record, which also works once its
+    // counter is gone
+    CloseContribution,
 }
 
 impl CounterInstruction {
+    // Decode an instruction, rejecting anything that is not exactly its canonical
+    // encoding, including trailing bytes
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        // Get the instruction variant from the first byte
-        let (&variant, rest) = input
-            .split_first()
-            .ok_or(ProgramError::InvalidInstructionData)?;
-
-        // Match instruction type and parse the remaining bytes based on the variant
-        match variant {
-            0 => {
-                // For InitializeCounter, parse a u64 from the remaining bytes
-                let initial_value = u64::from_le_bytes(
-                    rest.try_into()
-                        .map_err(|_| ProgramError::InvalidInstructionData)?,
-                );
-                Ok(Self::InitializeCounter { initial_value })
-            }
-            1 => Ok(Self::IncrementCounter), // No additional data needed
-            _ => Err(ProgramError::InvalidInstructionData),
+        Self::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)
+    }
+
+    // Encode the instruction in its canonical format
+    pub fn pack(&self) -> Vec<u8> {
+        borsh::to_vec(self).expect("writing to a Vec cannot fail")
+    }
+
+    // Whether the instruction touches a counter and is therefore blocked by the global pause.
+    // Every such instruction returns the resulting count as return data.
+    pub fn is_counter_mutation(&self) -> bool {
+        self.config_position().is_some()
+    }
+
+    // Index of the program config in the instruction's accounts, for the instructions
+    // that touch a counter. Its address is checked there before the instruction runs,
+    // so handlers can skip that position without looking at it.
+    pub fn config_position(&self) -> Option<usize> {
+        match self {
+            Self::InitializeConfig { .. }
+            | Self::SetPaused { .. }
+            | Self::UpdateConfig { .. }
+            | Self::GetCounter
+            | Self::GetHistory
+            | Self::CloseContribution => None,
+            Self::Aggregate => Some(1),
+            Self::CloseCounter => Some(3),
+            Self::InitializeCounter { .. }
+            | Self::MigrateCounter
+            | Self::Contribute { .. }
+            | Self::RevokeDelegate => Some(4),
+            Self::InitializeShard { .. } | Self::ApproveDelegate { .. } => Some(5),
+            // Everything else takes the counter, its authority, then the config, and so
+            // do increments through a shard
+            _ => Some(2),
+        }
+    }
+}
+
+// Range a counter must stay in, and what happens when an update leaves it
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterBounds {
+    pub min: u64,
+    pub max: u64,
+    pub policy: OverflowPolicy,
+}
+
+impl Default for CounterBounds {
+    fn default() -> Self {
+        Self {
+            min: 0,
+            max: u64::MAX,
+            policy: OverflowPolicy::Error,
+        }
+    }
+}
+
+impl CounterBounds {
+    // Make sure `value` is inside the bounds
+    pub fn check(&self, value: u64) -> Result<(), ProgramError> {
+        if value < self.min {
+            return Err(CounterError::Underflow.into());
+        }
+        if value > self.max {
+            return Err(CounterError::Overflow.into());
+        }
+        Ok(())
+    }
+
+    // Add `amount` to `count` following the overflow policy
+    pub fn increment(&self, count: u64, amount: u64) -> Result<u64, ProgramError> {
+        match count.checked_add(amount).filter(|&value| value <= self.max) {
+            Some(value) => Ok(value),
+            None => match self.policy {
+                OverflowPolicy::Error => Err(CounterError::Overflow.into()),
+                OverflowPolicy::Saturate => Ok(self.max),
+                OverflowPolicy::Wrap => {
+                    let offset = (count - self.min) as u128 + amount as u128;
+                    Ok(self.min + (offset % self.range_size()) as u64)
+                }
+            },
+        }
+    }
+
+    // Subtract `amount` from `count` following the overflow policy
+    pub fn decrement(&self, count: u64, amount: u64) -> Result<u64, ProgramError> {
+        match count.checked_sub(amount).filter(|&value| value >= self.min) {
+            Some(value) => Ok(value),
+            None => match self.policy {
+                OverflowPolicy::Error => Err(CounterError::Underflow.into()),
+                OverflowPolicy::Saturate => Ok(self.min),
+                OverflowPolicy::Wrap => {
+                    let size = self.range_size();
+                    let offset = (count - self.min) as u128 + size - amount as u128 % size;
+                    Ok(self.min + (offset % size) as u64)
+                }
+            },
+        }
+    }
+
+    // Number of values in [min, max], which is 2^64 for an unbounded counter
+    fn range_size(&self) -> u128 {
+        (self.max - self.min) as u128 + 1
+    }
+}
+
+// How often a counter may be incremented: at most `max_increments` increment
+// instructions per window of `window` slots or seconds, however large each one is
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub max_increments: u64,
+    pub window: u64,
+    pub unit: WindowUnit,
+}
+
+// Lamports every increment of a counter costs its signer, paid to the treasury
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterFee {
+    pub price: u64,
+    pub treasury: Pubkey,
+}
+
+// Token a signer has to hold to increment a gated counter: a token account it owns with
+// at least `min_balance` of `mint`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenGate {
+    pub mint: Pubkey,
+    pub min_balance: u64,
+}
+
+// Most signers a counter's multisig can register
+pub const MAX_SIGNERS: usize = 8;
+
+// A counter's M-of-N authority: at least `threshold` of `signers` have to sign
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Multisig<'a> {
+    pub threshold: u8,
+    pub signers: &'a [Pubkey],
+}
+
+// What the window of a rate limit is measured in
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WindowUnit {
+    #[default]
+    Slots,
+    // Unix time from the Clock sysvar
+    Seconds,
+}
+
+impl RateLimit {
+    // Count an increment made at `clock` against the window starting at `window_start`
+    // with `window_increments` made so far. Returns the updated window, which is a new
+    // one once the given window has passed.
+    fn use_window(
+        &self,
+        clock: &Clock,
+        window_start: u64,
+        window_increments: u64,
+    ) -> Result<(u64, u64), ProgramError> {
+        let now = self.unit.now(clock);
+        let (window_start, window_increments) = if now.saturating_sub(window_start) >= self.window {
+            (now, 0)
+        } else {
+            (window_start, window_increments)
+        };
+        if window_increments >= self.max_increments {
+            msg!(
+                "Rate limit of {} increments reached, the window started at {}",
+                self.max_increments,
+                window_start
+            );
+            return Err(CounterError::RateLimited.into());
+        }
+        Ok((window_start, window_increments + 1))
+    }
+}
+
+impl WindowUnit {
+    // Current time in this unit
+    fn now(&self, clock: &Clock) -> u64 {
+        match self {
+            Self::Slots => clock.slot,
+            Self::Seconds => u64::try_from(clock.unix_timestamp).unwrap_or(0),
+        }
+    }
+}
+
+// What to do when an increment or decrement leaves the counter's bounds
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    // Fail with Overflow or Underflow
+    #[default]
+    Error,
+    // Stop at the bound that was crossed
+    Saturate,
+    // Continue from the other bound, modulo the size of the range
+    Wrap,
+}
+
+// Parse a little-endian u64 that must take up the whole input
+fn unpack_u64(input: &[u8]) -> Result<u64, ProgramError> {
+    input
+        .try_into()
+        .map(u64::from_le_bytes)
+        .map_err(|_| ProgramError::InvalidInstructionData)
+}
+
+// Errors specific to the counter program. The codes are part of the program's
+// interface, so existing variants must never be renumbered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterError {
+    Overflow = 0,
+    Underflow = 1,
+    Unauthorized = 2,
+    AlreadyInitialized = 3,
+    WrongAccountSize = 4,
+    Frozen = 5,
+    InvalidAccountType = 6,
+    OutdatedVersion = 7,
+    Paused = 8,
+    RateLimited = 9,
+    DelegateExpired = 10,
+    AllowanceExceeded = 11,
+    TokenGated = 12,
+}
+
+impl CounterError {
+    // Decode a `ProgramError::Custom` code back into a counter error
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(Self::Overflow),
+            1 => Some(Self::Underflow),
+            2 => Some(Self::Unauthorized),
+            3 => Some(Self::AlreadyInitialized),
+            4 => Some(Self::WrongAccountSize),
+            5 => Some(Self::Frozen),
+            6 => Some(Self::InvalidAccountType),
+            7 => Some(Self::OutdatedVersion),
+            8 => Some(Self::Paused),
+            9 => Some(Self::RateLimited),
+            10 => Some(Self::DelegateExpired),
+            11 => Some(Self::AllowanceExceeded),
+            12 => Some(Self::TokenGated),
+            _ => None,
+        }
+    }
+
+    // Human readable description of the error
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Overflow => "Counter would go above its maximum value",
+            Self::Underflow => "Counter would go below its minimum value",
+            Self::Unauthorized => "Signer is not the counter authority",
+            Self::AlreadyInitialized => "Counter account is already initialized",
+            Self::WrongAccountSize => "Counter account has the wrong size",
+            Self::Frozen => "Counter is frozen",
+            Self::InvalidAccountType => "Account is not a counter",
+            Self::OutdatedVersion => "Counter account must be migrated first",
+            Self::Paused => "Counter program is paused",
+            Self::RateLimited => "Counter rate limit reached, try again in the next window",
+            Self::DelegateExpired => "Delegate's grant has expired",
+            Self::AllowanceExceeded => "Delegate has no increments left in its allowance",
+            Self::TokenGated => "Signer does not hold enough of the counter's gate token",
+        }
+    }
+}
+
+impl std::fmt::Display for CounterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for CounterError {}
+
+impl From<CounterError> for ProgramError {
+    fn from(e: CounterError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl TryFrom<&ProgramError> for CounterError {
+    type Error = ();
+
+    fn try_from(e: &ProgramError) -> Result<Self, Self::Error> {
+        match e {
+            ProgramError::Custom(code) => Self::from_code(*code).ok_or(()),
+            _ => Err(()),
         }
     }
 }
 
-// Initialize a new counter account
+// Seed prefix of counter addresses
+pub const COUNTER_SEED: &[u8] = b"counter";
+
+// Derive the address of an authority's counter with the given index
+pub fn find_counter_address(program_id: &Pubkey, authority: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[COUNTER_SEED, authority.as_ref(), &index.to_le_bytes()],
+        program_id,
+    )
+}
+
+// Seed prefix of shard addresses
+pub const SHARD_SEED: &[u8] = b"shard";
+
+// Most shards a counter can have, all of them fit in one Aggregate transaction
+pub const MAX_SHARDS: u8 = 16;
+
+// Derive the address of a counter's shard
+pub fn find_shard_address(program_id: &Pubkey, counter: &Pubkey, shard: u8) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SHARD_SEED, counter.as_ref(), &[shard]], program_id)
+}
+
+// Seed prefix of contribution addresses
+pub const CONTRIBUTION_SEED: &[u8] = b"contribution";
+
+// Derive the address of the record of what `contributor` added to a counter
+pub fn find_contribution_address(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    contributor: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[CONTRIBUTION_SEED, counter.as_ref(), contributor.as_ref()],
+        program_id,
+    )
+}
+
+// Seed prefix of delegate addresses
+pub const DELEGATE_SEED: &[u8] = b"delegate";
+
+// Derive the address of the record of what `delegate` may add to a counter
+pub fn find_delegate_address(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    delegate: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DELEGATE_SEED, counter.as_ref(), delegate.as_ref()],
+        program_id,
+    )
+}
+
+// Seed of the address of the account handing out counter generations
+pub const GENERATION_SEED: &[u8] = b"generation";
+
+// Derive the address of the account handing out counter generations
+pub fn find_generation_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[GENERATION_SEED], program_id)
+}
+
+// Initialize a new counter account at its program derived address
 fn process_initialize_counter(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     initial_value: u64,
+    index: u64,
+    bounds: Option<CounterBounds>,
 ) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
     let counter_account = next_account_info(accounts_iter)?;
     let payer_account = next_account_info(accounts_iter)?;
     let system_program = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let _config_account = next_account_info(accounts_iter)?;
+    let generation_account = next_account_info(accounts_iter)?;
+
+    // Verify system program
+    if system_program.key != &solana_program::system_program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // The authority signs for the counters created under its address. It can be a
+    // wallet or a PDA of a program calling us through CPI.
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // Verify the counter address is derived from the authority and the index
+    let (expected_address, bump) = find_counter_address(program_id, authority_account.key, index);
+    if counter_account.key != &expected_address {
+        msg!("Counter address does not match its seeds");
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Refuse to create a counter that already exists
+    if counter_account.owner == program_id || !counter_account.data_is_empty() {
+        return Err(CounterError::AlreadyInitialized.into());
+    }
+
+    // The initial value has to be inside the bounds
+    let bounds = bounds.unwrap_or_default();
+    if bounds.min > bounds.max {
+        msg!("Counter minimum is above its maximum");
+        return Err(ProgramError::InvalidArgument);
+    }
+    bounds.check(initial_value)?;
 
     // Size of our counter account
-    let account_space = 8; // Size in bytes to store a u64
+    let account_space = CounterAccount::LEN;
 
     // Calculate minimum balance for rent exemption
     let rent = Rent::get()?;
     let required_lamports = rent.minimum_balance(account_space);
 
-    // Create the counter account
-    invoke(
+    // Create the counter account, signing for its address with the seeds
+    invoke_signed(
         &system_instruction::create_account(
             payer_account.key,    // Account paying for the new account
             counter_account.key,  // Account to be created
@@ -96,12 +675,22 @@ fn process_initialize_counter(
             counter_account.clone(),
             system_program.clone(),
         ],
+        &[&[
+            COUNTER_SEED,
+            authority_account.key.as_ref(),
+            &index.to_le_bytes(),
+            &[bump],
+        ]],
     )?;
 
     // Create a new CounterAccount struct with the initial value
-    let counter_data = CounterAccount {
-        count: initial_value,
-    };
+    let mut counter_data = CounterAccount::new(initial_value, *authority_account.key, bump, bounds);
+    counter_data.generation = next_generation(
+        program_id,
+        generation_account,
+        payer_account,
+        system_program,
+    )?;
 
     // Get a mutable reference to the counter account's data
     let mut account_data = &mut counter_account.data.borrow_mut()[..];
@@ -110,137 +699,6464 @@ fn process_initialize_counter(
     counter_data.serialize(&mut account_data)?;
 
     msg!("Counter initialized with value: {}", initial_value);
+    return_count(initial_value);
+
+    Ok(())
+}
+
+// Hand out the next counter generation. Every counter gets one no other counter had,
+// so a counter created again at the same address, even in the same slot, never takes
+// over the records of the one before. The first counter creation pays for the account.
+fn next_generation<'info>(
+    program_id: &Pubkey,
+    generation_account: &AccountInfo<'info>,
+    payer_account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<u64, ProgramError> {
+    let mut generation_data = if generation_account.owner == program_id {
+        let generation_data = GenerationAccount::unpack(&generation_account.data.borrow())?;
+        let expected_address = Pubkey::create_program_address(
+            &[GENERATION_SEED, &[generation_data.bump]],
+            program_id,
+        )?;
+        if generation_account.key != &expected_address {
+            msg!("Generation address does not match its seeds");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        generation_data
+    } else {
+        let (expected_address, bump) = find_generation_address(program_id);
+        if generation_account.key != &expected_address {
+            msg!("Generation address does not match its seeds");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                payer_account.key,
+                generation_account.key,
+                rent.minimum_balance(GenerationAccount::LEN),
+                GenerationAccount::LEN as u64,
+                program_id,
+            ),
+            &[
+                payer_account.clone(),
+                generation_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[GENERATION_SEED, &[bump]]],
+        )?;
+        GenerationAccount::new(bump)
+    };
+
+    // Generations start at 1, 0 is left to counters migrated from before generations
+    generation_data.last = generation_data
+        .last
+        .checked_add(1)
+        .ok_or(CounterError::Overflow)?;
+    generation_data.serialize(&mut &mut generation_account.data.borrow_mut()[..])?;
+    Ok(generation_data.last)
+}
+
+// Add `amount` to an existing counter's value
+fn process_increment_counter(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    // Increments to a sharded counter may go to one of its shards instead
+    let is_shard = accounts.first().is_some_and(|account| {
+        account.owner == program_id
+            && account
+                .try_borrow_data()
+                .is_ok_and(|data| data.starts_with(&ShardAccount::DISCRIMINATOR))
+    });
+    if is_shard {
+        return process_increment_shard(program_id, accounts, amount);
+    }
+
+    #[cfg(feature = "borsh-increment")]
+    return process_increment_counter_borsh(program_id, accounts, amount);
+    #[cfg(not(feature = "borsh-increment"))]
+    process_increment_counter_zero_copy(program_id, accounts, amount)
+}
+
+// Increment a counter in place, through its fixed layout. Builds with the
+// `borsh-increment` feature keep it, and the `PodCounterAccount` methods only it uses.
+#[cfg_attr(feature = "borsh-increment", allow(dead_code))]
+fn process_increment_counter_zero_copy(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let _config_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Increments are the hottest path, so unlike `update_counter` this works on the
+    // account data in place rather than deserializing and reserializing the counter
+    let mut data = counter_account.data.borrow_mut();
+    let counter_data = PodCounterAccount::load_mut(&mut data)?;
+
+    // Only the counter's authority or a delegate may change it, and only while it is not
+    // frozen
+    check_increment_authority(
+        program_id,
+        counter_account.key,
+        counter_data.generation(),
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+    check_token_gate(counter_data.token_gate(), authority_account, accounts)?;
+
+    counter_data.use_rate_limit()?;
+    // The authority pays
+    charge_fee(counter_data.fee(), authority_account, accounts_iter)?;
 
+    // Compute the new counter value and keep a record of the change
+    let previous = counter_data.count();
+    let count = counter_data.bounds().increment(previous, amount)?;
+    counter_data.set_count(count);
+    counter_data.record_change(*authority_account.key, previous)?;
+
+    return_count(count);
+    msg!("Counter incremented to: {}", count);
     Ok(())
 }
 
-// Update an existing counter's value
-fn process_increment_counter(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+// `process_increment_counter` with a Borsh round trip of the whole counter instead of
+// the in place update, as increments worked before the zero-copy layout. Only builds
+// with the `borsh-increment` feature use it, to measure what the layout saves.
+#[cfg(feature = "borsh-increment")]
+fn process_increment_counter_borsh(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
     let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let _config_account = next_account_info(accounts_iter)?;
 
     // Verify account ownership
     if counter_account.owner != program_id {
         return Err(ProgramError::IncorrectProgramId);
     }
 
-    // Mutable borrow the account data
     let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
 
-    // Deserialize the account data into our CounterAccount struct
-    let mut counter_data: CounterAccount = CounterAccount::try_from_slice(&data)?;
+    check_increment_authority(
+        program_id,
+        counter_account.key,
+        counter_data.generation,
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+    check_token_gate(counter_data.token_gate(), authority_account, accounts)?;
 
-    // Increment the counter value
-    counter_data.count = counter_data
-        .count
-        .checked_add(1)
-        .ok_or(ProgramError::InvalidAccountData)?;
+    counter_data.use_rate_limit()?;
+    charge_fee(counter_data.fee(), authority_account, accounts_iter)?;
 
-    // Serialize the updated counter data back into the account
+    let previous = counter_data.count;
+    counter_data.count = counter_data.bounds().increment(previous, amount)?;
+    counter_data.record_change(*authority_account.key, previous)?;
     counter_data.serialize(&mut &mut data[..])?;
 
+    return_count(counter_data.count);
     msg!("Counter incremented to: {}", counter_data.count);
     Ok(())
 }
 
-// Struct representing our counter account's data
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
-pub struct CounterAccount {
-    count: u64,
+// Subtract `amount` from an existing counter's value
+fn process_decrement_counter(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let count = update_counter(program_id, accounts, |counter_data| {
+        counter_data.bounds().decrement(counter_data.count, amount)
+    })?;
+
+    msg!("Counter decremented to: {}", count);
+    Ok(())
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use solana_program_test::*;
-    use solana_sdk::{
-        instruction::{AccountMeta, Instruction},
-        signature::{Keypair, Signer},
-        system_program,
-        transaction::Transaction,
-    };
+// Move an existing counter back to its minimum
+fn process_reset_counter(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let count = update_counter(program_id, accounts, |counter_data| Ok(counter_data.min))?;
 
-    #[tokio::test]
-    async fn test_counter_program() {
-        let program_id = Pubkey::new_unique();
-        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
-            "counter_program",
-            program_id,
-            processor!(process_instruction),
-        )
-        .start()
-        .await;
+    msg!("Counter reset to: {}", count);
+    Ok(())
+}
 
-        // Create a new keypair to use as the address for our counter account
-        let counter_keypair = Keypair::new();
-        let initial_value: u64 = 42;
+// Overwrite an existing counter's value
+fn process_set_value(program_id: &Pubkey, accounts: &[AccountInfo], value: u64) -> ProgramResult {
+    let count = update_counter(program_id, accounts, |counter_data| {
+        counter_data.bounds().check(value)?;
+        Ok(value)
+    })?;
 
-        // Step 1: Initialize the counter
-        println!("Testing counter initialization...");
+    msg!("Counter set to: {}", count);
+    Ok(())
+}
 
-        // Create initialization instruction
-        let mut init_instruction_data = vec![0]; // 0 = initialize instruction
-        init_instruction_data.extend_from_slice(&initial_value.to_le_bytes());
+// Load a counter, check its authority, apply `update` to the count and store the result
+fn update_counter<F>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    update: F,
+) -> Result<u64, ProgramError>
+where
+    F: FnOnce(&mut CounterAccount) -> Result<u64, ProgramError>,
+{
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
 
-        let initialize_instruction = Instruction::new_with_bytes(
-            program_id,
-            &init_instruction_data,
-            vec![
-                AccountMeta::new(counter_keypair.pubkey(), true),
-                AccountMeta::new(payer.pubkey(), true),
-                AccountMeta::new_readonly(system_program::id(), false),
-            ],
-        );
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
-        // Send transaction with initialize instruction
-        let mut transaction =
-            Transaction::new_with_payer(&[initialize_instruction], Some(&payer.pubkey()));
-        transaction.sign(&[&payer, &counter_keypair], recent_blockhash);
-        banks_client.process_transaction(transaction).await.unwrap();
+    // Mutable borrow the account data
+    let mut data = counter_account.data.borrow_mut();
 
-        // Check account data
-        let account = banks_client
-            .get_account(counter_keypair.pubkey())
-            .await
-            .expect("Failed to get counter account");
+    // Deserialize the account data into our CounterAccount struct
+    let mut counter_data = CounterAccount::unpack(&data)?;
 
-        if let Some(account_data) = account {
-            let counter: CounterAccount = CounterAccount::try_from_slice(&account_data.data)
-                .expect("Failed to deserialize counter data");
-            assert_eq!(counter.count, 42);
-            println!(
-                "✅ Counter initialized successfully with value: {}",
-                counter.count
-            );
-        }
+    // Only the counter's authority may change it, and only while it is not frozen
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
 
-        // Step 2: Increment the counter
-        println!("Testing counter increment...");
+    // Compute the new counter value and keep a record of the change
+    let previous = counter_data.count;
+    counter_data.count = update(&mut counter_data)?;
+    counter_data.record_change(*authority_account.key, previous)?;
 
-        // Create increment instruction
-        let increment_instruction = Instruction::new_with_bytes(
-            program_id,
-            &[1], // 1 = increment instruction
-            vec![AccountMeta::new(counter_keypair.pubkey(), true)],
-        );
+    // Serialize the updated counter data back into the account
+    counter_data.serialize(&mut &mut data[..])?;
 
-        // Send transaction with increment instruction
-        let mut transaction =
-            Transaction::new_with_payer(&[increment_instruction], Some(&payer.pubkey()));
-        transaction.sign(&[&payer, &counter_keypair], recent_blockhash);
+    return_count(counter_data.count);
+    Ok(counter_data.count)
+}
+
+// Add `amount` to a shard's pending increments. The counter itself is only read, so
+// increments to different shards do not contend for a write lock. Returns the count
+// the counter would have with this shard's pending increments folded in. The pending
+// increments of the other shards are not counted, those accounts are not passed.
+// The change is recorded in the counter's history once Aggregate folds the shard in.
+fn process_increment_shard(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let shard_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let _config_account = next_account_info(accounts_iter)?;
+    let counter_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if shard_account.owner != program_id || counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let counter_data = CounterAccount::unpack(&counter_account.data.borrow())?;
+    check_increment_authority(
+        program_id,
+        counter_account.key,
+        counter_data.generation,
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+    check_token_gate(counter_data.token_gate(), authority_account, accounts)?;
+
+    charge_fee(counter_data.fee(), authority_account, accounts_iter)?;
+
+    let mut data = shard_account.data.borrow_mut();
+    let mut shard_data = ShardAccount::unpack(&data)?;
+    if shard_data.counter != *counter_account.key {
+        msg!("Shard belongs to another counter");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Fail early when this shard alone would take the counter out of its bounds
+    let pending = shard_data
+        .pending
+        .checked_add(amount)
+        .ok_or(CounterError::Overflow)?;
+    let value = counter_data
+        .bounds()
+        .increment(counter_data.count, pending)?;
+
+    shard_data.pending = pending;
+    shard_data.serialize(&mut &mut data[..])?;
+
+    msg!("Shard {} pending increments: {}", shard_data.shard, pending);
+    return_count(value);
+    Ok(())
+}
+
+// Read a counter's value without changing it
+fn process_get_counter(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let counter_data = CounterAccount::unpack(&counter_account.data.borrow())?;
+
+    msg!("Counter value: {}", counter_data.count);
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Read a counter's recent changes, oldest first, without changing it
+fn process_get_history(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let counter_data = CounterAccount::unpack(&counter_account.data.borrow())?;
+    let history: Vec<&HistoryEntry> = counter_data.history().collect();
+
+    msg!("Counter history entries: {}", history.len());
+    set_return_data(&borsh::to_vec(&history)?);
+    Ok(())
+}
+
+// Make `payer` pay a counter's fee to its treasury, if the counter has a fee. The
+// treasury and the system program are the next accounts of `accounts_iter`.
+fn charge_fee<'a, 'info: 'a, I>(
+    fee: Option<CounterFee>,
+    payer: &AccountInfo<'info>,
+    accounts_iter: &mut I,
+) -> ProgramResult
+where
+    I: Iterator<Item = &'a AccountInfo<'info>>,
+{
+    let Some(fee) = fee else {
+        return Ok(());
+    };
+    let treasury_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // Verify system program
+    if system_program.key != &solana_program::system_program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if treasury_account.key != &fee.treasury {
+        msg!("Fee must be paid to the counter treasury: {}", fee.treasury);
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    invoke(
+        &system_instruction::transfer(payer.key, treasury_account.key, fee.price),
+        &[
+            payer.clone(),
+            treasury_account.clone(),
+            system_program.clone(),
+        ],
+    )?;
+    msg!("Paid a fee of {} lamports", fee.price);
+    Ok(())
+}
+
+// Publish a counter's value as return data for programs calling us through CPI
+fn return_count(count: u64) {
+    set_return_data(&count.to_le_bytes());
+}
+
+// Hand the counter over to a new authority, or renounce it
+fn process_set_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: Option<Pubkey>,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    // A renounced counter stores the default pubkey, which nobody can sign for. Either
+    // way the new authority is a single key, so a multisig is dropped.
+    counter_data.authority = new_authority.unwrap_or_default();
+    counter_data.threshold = 0;
+    counter_data.signer_count = 0;
+    counter_data.signers = [Pubkey::default(); MAX_SIGNERS];
+    counter_data.serialize(&mut &mut data[..])?;
+
+    match new_authority {
+        Some(authority) => msg!("Counter authority set to: {}", authority),
+        None => msg!("Counter authority renounced"),
+    }
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Limit how often a counter may be incremented, or lift the limit
+fn process_set_rate_limit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    rate_limit: Option<RateLimit>,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    // Shard increments leave the counter untouched, so they could not be rate limited
+    if rate_limit.is_some() && counter_data.shards > 0 {
+        msg!("Sharded counters cannot be rate limited");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if rate_limit.is_some_and(|rate_limit| rate_limit.max_increments == 0 || rate_limit.window == 0)
+    {
+        msg!("Rate limit needs a window and at least one increment per window");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // A window of 0 stores no limit, and the new limit starts with a fresh window
+    let rate_limit = rate_limit.unwrap_or(RateLimit {
+        max_increments: 0,
+        window: 0,
+        unit: WindowUnit::Slots,
+    });
+    counter_data.rate_limit_max = rate_limit.max_increments;
+    counter_data.rate_limit_window = rate_limit.window;
+    counter_data.rate_limit_unit = rate_limit.unit;
+    counter_data.window_start = 0;
+    counter_data.window_increments = 0;
+    counter_data.serialize(&mut &mut data[..])?;
+
+    match counter_data.rate_limit() {
+        Some(rate_limit) => msg!(
+            "Counter rate limit set to {} increments per {} {:?}",
+            rate_limit.max_increments,
+            rate_limit.window,
+            rate_limit.unit
+        ),
+        None => msg!("Counter rate limit removed"),
+    }
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Set the price of increments and the treasury it is paid to, or make them free
+fn process_set_fee(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    fee: Option<CounterFee>,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    // A price of 0 stores no fee, and the counter cannot be its own treasury
+    if fee.is_some_and(|fee| fee.price == 0 || fee.treasury == *counter_account.key) {
+        msg!("Fee needs a price and a treasury other than the counter");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let fee = fee.unwrap_or(CounterFee {
+        price: 0,
+        treasury: Pubkey::default(),
+    });
+    counter_data.fee_price = fee.price;
+    counter_data.treasury = fee.treasury;
+    counter_data.serialize(&mut &mut data[..])?;
+
+    match counter_data.fee() {
+        Some(fee) => msg!(
+            "Counter fee set to {} lamports, paid to: {}",
+            fee.price,
+            fee.treasury
+        ),
+        None => msg!("Counter fee removed"),
+    }
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Only let holders of a token increment a counter, or lift the gate
+fn process_set_token_gate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    gate: Option<TokenGate>,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    // The default mint stores no gate, and holding none of a token is no gate either
+    if gate.is_some_and(|gate| gate.mint == Pubkey::default() || gate.min_balance == 0) {
+        msg!("Token gate needs a mint and a balance of at least 1");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let gate = gate.unwrap_or(TokenGate {
+        mint: Pubkey::default(),
+        min_balance: 0,
+    });
+    counter_data.gate_mint = gate.mint;
+    counter_data.gate_min_balance = gate.min_balance;
+    counter_data.serialize(&mut &mut data[..])?;
+
+    match counter_data.token_gate() {
+        Some(gate) => msg!(
+            "Counter gated to holders of {} of mint: {}",
+            gate.min_balance,
+            gate.mint
+        ),
+        None => msg!("Counter token gate removed"),
+    }
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Open a counter to contributions from anyone, or back to its authority only
+fn process_set_shared(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    shared: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    counter_data.shared = shared;
+    counter_data.serialize(&mut &mut data[..])?;
+
+    if shared {
+        msg!("Counter shared at: {}", counter_data.count);
+    } else {
+        msg!("Counter unshared at: {}", counter_data.count);
+    }
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Register another signer of the counter's multisig
+fn process_add_signer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signer: Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    if signer == Pubkey::default() || counter_data.signers().contains(&signer) {
+        msg!("Signer must be a new, non-default pubkey: {}", signer);
+        return Err(ProgramError::InvalidArgument);
+    }
+    if counter_data.signer_count as usize == MAX_SIGNERS {
+        msg!("Counter already has the maximum of {} signers", MAX_SIGNERS);
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    counter_data.signers[counter_data.signer_count as usize] = signer;
+    counter_data.signer_count += 1;
+    counter_data.serialize(&mut &mut data[..])?;
+
+    msg!(
+        "Counter signer added: {}, {} of {} have to sign",
+        signer,
+        counter_data.threshold,
+        counter_data.signer_count
+    );
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Deregister one of the signers of the counter's multisig
+fn process_remove_signer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signer: Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    let Some(position) = counter_data.signers().iter().position(|key| *key == signer) else {
+        msg!("Not a signer of the counter: {}", signer);
+        return Err(ProgramError::InvalidArgument);
+    };
+    // Removing a signer never leaves the threshold out of reach
+    if counter_data.signer_count == counter_data.threshold {
+        msg!("Lower the threshold before removing one of its signers");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Keep the remaining signers in the order they were added
+    let count = counter_data.signer_count as usize;
+    counter_data
+        .signers
+        .copy_within(position + 1..count, position);
+    counter_data.signers[count - 1] = Pubkey::default();
+    counter_data.signer_count -= 1;
+    counter_data.serialize(&mut &mut data[..])?;
+
+    msg!(
+        "Counter signer removed: {}, {} of {} have to sign",
+        signer,
+        counter_data.threshold,
+        counter_data.signer_count
+    );
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Change how many of the counter's signers have to sign. From 0 to a threshold hands the
+// counter from its authority key to the multisig, and back the other way.
+fn process_set_threshold(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    threshold: u8,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    if threshold > counter_data.signer_count {
+        msg!(
+            "Threshold of {} is more than the {} signers",
+            threshold,
+            counter_data.signer_count
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    counter_data.threshold = threshold;
+    counter_data.serialize(&mut &mut data[..])?;
+
+    match counter_data.multisig() {
+        Some(multisig) => msg!(
+            "Counter multisig set to {} of {} signers",
+            multisig.threshold,
+            multisig.signers.len()
+        ),
+        None => msg!("Counter multisig removed"),
+    }
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Let `delegate` increment the counter within an allowance and until an expiry,
+// creating its record or replacing the grant it has
+fn process_approve_delegate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    delegate: Pubkey,
+    allowance: Option<u64>,
+    expires_at: Option<i64>,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let delegate_account = next_account_info(accounts_iter)?;
+    let counter_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify system program
+    if system_program.key != &solana_program::system_program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let counter_data = CounterAccount::unpack(&counter_account.data.borrow())?;
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    // A grant has to allow something when it is made
+    let clock = Clock::get()?;
+    if delegate == Pubkey::default() || allowance == Some(0) {
+        msg!("Delegate must be a non-default pubkey with a non-zero allowance");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if expires_at.is_some_and(|expires_at| expires_at <= clock.unix_timestamp) {
+        msg!(
+            "Delegate expiry is not after the current time {}",
+            clock.unix_timestamp
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Verify the record address is derived from the counter and the delegate
+    let (expected_address, bump) =
+        find_delegate_address(program_id, counter_account.key, &delegate);
+    if delegate_account.key != &expected_address {
+        msg!("Delegate address does not match its seeds");
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // The first approval creates the record, later ones replace the grant in it. Whoever
+    // funded the record stays its payer.
+    let payer = if delegate_account.owner != program_id {
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                payer_account.key,
+                delegate_account.key,
+                rent.minimum_balance(DelegateAccount::LEN),
+                DelegateAccount::LEN as u64,
+                program_id,
+            ),
+            &[
+                payer_account.clone(),
+                delegate_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                DELEGATE_SEED,
+                counter_account.key.as_ref(),
+                delegate.as_ref(),
+                &[bump],
+            ]],
+        )?;
+        *payer_account.key
+    } else {
+        DelegateAccount::unpack(&delegate_account.data.borrow())?.payer
+    };
+
+    let delegate_data = DelegateAccount::new(
+        *counter_account.key,
+        counter_data.generation,
+        delegate,
+        payer,
+        bump,
+        allowance,
+        expires_at,
+    );
+    delegate_data.serialize(&mut &mut delegate_account.data.borrow_mut()[..])?;
+
+    msg!(
+        "Counter delegate approved: {}, allowance {:?}, expires at {:?}",
+        delegate,
+        allowance,
+        expires_at
+    );
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// End a delegate's grant, closing its record and sending the rent to a destination
+// account. Frozen counters can still revoke, so a leaked delegate key can always be cut
+// off. Once the counter the grant was made on is closed, anyone can close the record,
+// and its rent goes back to whoever paid for it.
+fn process_revoke_delegate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let delegate_account = next_account_info(accounts_iter)?;
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let destination_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if delegate_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let delegate_data = DelegateAccount::unpack(&delegate_account.data.borrow())?;
+    if delegate_data.counter != *counter_account.key {
+        msg!("Delegate record belongs to another counter");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // The counter was closed, and maybe created again, since the grant was made
+    let counter_data = if counter_account.owner == program_id {
+        Some(CounterAccount::unpack(&counter_account.data.borrow())?)
+    } else {
+        None
+    };
+    let Some(counter_data) =
+        counter_data.filter(|counter_data| counter_data.generation == delegate_data.generation)
+    else {
+        if *destination_account.key != delegate_data.payer {
+            msg!(
+                "Rent of a record left from a closed counter goes to its payer: {}",
+                delegate_data.payer
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        close_account(delegate_account, destination_account)?;
+        msg!(
+            "Stale counter delegate record closed: {}",
+            delegate_data.delegate
+        );
+        return Ok(());
+    };
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+
+    close_account(delegate_account, destination_account)?;
+
+    msg!("Counter delegate revoked: {}", delegate_data.delegate);
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Increment a counter on behalf of the signer and add the amount to the signer's
+// contribution record, creating the record on their first contribution. Amounts are
+// not limited, contributions weigh by what was added.
+fn process_contribute(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let contributor_account = next_account_info(accounts_iter)?;
+    let contribution_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let _config_account = next_account_info(accounts_iter)?;
+
+    // Verify system program
+    if system_program.key != &solana_program::system_program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if !contributor_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut counter_data = CounterAccount::unpack(&counter_account.data.borrow())?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    // Only shared counters take contributions from anyone but their authority
+    if !counter_data.shared {
+        check_authority(
+            counter_data.authority(),
+            counter_data.multisig(),
+            contributor_account,
+            accounts,
+        )?;
+    }
+    check_token_gate(counter_data.token_gate(), contributor_account, accounts)?;
+
+    // Verify the record address is derived from the counter and the contributor
+    let (expected_address, bump) =
+        find_contribution_address(program_id, counter_account.key, contributor_account.key);
+    if contribution_account.key != &expected_address {
+        msg!("Contribution address does not match its seeds");
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // The first contribution creates the record, paid for by the contributor
+    if contribution_account.owner != program_id {
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                contributor_account.key,
+                contribution_account.key,
+                rent.minimum_balance(ContributionAccount::LEN),
+                ContributionAccount::LEN as u64,
+                program_id,
+            ),
+            &[
+                contributor_account.clone(),
+                contribution_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                CONTRIBUTION_SEED,
+                counter_account.key.as_ref(),
+                contributor_account.key.as_ref(),
+                &[bump],
+            ]],
+        )?;
+        ContributionAccount::new(
+            *counter_account.key,
+            counter_data.generation,
+            *contributor_account.key,
+            bump,
+        )
+        .serialize(&mut &mut contribution_account.data.borrow_mut()[..])?;
+    }
+
+    let mut data = contribution_account.data.borrow_mut();
+    let mut contribution_data = ContributionAccount::unpack(&data)?;
+    if contribution_data.counter != *counter_account.key
+        || contribution_data.contributor != *contributor_account.key
+    {
+        msg!("Contribution record belongs to another counter or contributor");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // The record outlived an earlier counter at this address, its totals were for that one
+    if contribution_data.generation != counter_data.generation {
+        contribution_data = ContributionAccount::new(
+            *counter_account.key,
+            counter_data.generation,
+            *contributor_account.key,
+            bump,
+        );
+    }
+
+    counter_data.use_rate_limit()?;
+    charge_fee(counter_data.fee(), contributor_account, accounts_iter)?;
+    let previous = counter_data.count;
+    counter_data.count = counter_data
+        .bounds()
+        .increment(counter_data.count, amount)?;
+    counter_data.record_change(*contributor_account.key, previous)?;
+    counter_data.serialize(&mut &mut counter_account.data.borrow_mut()[..])?;
+
+    contribution_data.total = contribution_data
+        .total
+        .checked_add(amount)
+        .ok_or(CounterError::Overflow)?;
+    contribution_data.contributions = contribution_data
+        .contributions
+        .checked_add(1)
+        .ok_or(CounterError::Overflow)?;
+    contribution_data.serialize(&mut &mut data[..])?;
+
+    msg!(
+        "Counter incremented to: {}, {} contributed {} in total",
+        counter_data.count,
+        contributor_account.key,
+        contribution_data.total
+    );
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Close a contribution record and send its rent to the destination. Only the
+// contributor can close it, whether or not its counter still exists.
+fn process_close_contribution(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let contribution_account = next_account_info(accounts_iter)?;
+    let contributor_account = next_account_info(accounts_iter)?;
+    let destination_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if contribution_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if !contributor_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let contribution_data = ContributionAccount::unpack(&contribution_account.data.borrow())?;
+    if contribution_data.contributor != *contributor_account.key {
+        msg!("Contribution record belongs to another contributor");
+        return Err(CounterError::Unauthorized.into());
+    }
+
+    let lamports = close_account(contribution_account, destination_account)?;
+
+    msg!(
+        "Contribution record closed, {} lamports sent to: {}",
+        lamports,
+        destination_account.key
+    );
+    Ok(())
+}
+
+// Freeze or thaw a counter. Frozen counters can be read but not changed.
+fn process_set_frozen(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    frozen: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = counter_account.data.borrow_mut();
+    let mut counter_data = CounterAccount::unpack(&data)?;
+
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+
+    counter_data.frozen = frozen;
+    counter_data.serialize(&mut &mut data[..])?;
+
+    if frozen {
+        msg!("Counter frozen at: {}", counter_data.count);
+    } else {
+        msg!("Counter thawed at: {}", counter_data.count);
+    }
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Close a counter and send its rent to a destination account
+fn process_close_counter(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let destination_account = next_account_info(accounts_iter)?;
+    let _config_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let counter_data = CounterAccount::unpack(&counter_account.data.borrow())?;
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    // A sharded counter closes all of its shards too, they follow the config account
+    for shard in 0..counter_data.shards {
+        let shard_account = next_account_info(accounts_iter)?;
+        if shard_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let shard_data = ShardAccount::unpack(&shard_account.data.borrow())?;
+        if shard_data.counter != *counter_account.key || shard_data.shard != shard {
+            msg!("Expected shard {} of the counter", shard);
+            return Err(ProgramError::InvalidArgument);
+        }
+        close_account(shard_account, destination_account)?;
+    }
+
+    let lamports = close_account(counter_account, destination_account)?;
+
+    msg!(
+        "Counter closed, {} lamports sent to: {}",
+        lamports,
+        destination_account.key
+    );
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Move all of an account's lamports to `destination`, wipe its data and give it back
+// to the system program
+fn close_account(account: &AccountInfo, destination: &AccountInfo) -> Result<u64, ProgramError> {
+    // The lamports would be burned if the account was its own destination
+    if account.key == destination.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let lamports = account.lamports();
+    **destination.lamports.borrow_mut() = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    **account.lamports.borrow_mut() = 0;
+
+    account.data.borrow_mut().fill(0);
+    account.resize(0)?;
+    account.assign(&solana_program::system_program::ID);
+    Ok(lamports)
+}
+
+// Add the next shard to a counter, making room for increments that do not write-lock
+// the counter itself
+fn process_initialize_shard(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    shard: u8,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let shard_account = next_account_info(accounts_iter)?;
+    let counter_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // Verify system program
+    if system_program.key != &solana_program::system_program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut counter_data = CounterAccount::unpack(&counter_account.data.borrow())?;
+    check_authority(
+        counter_data.authority(),
+        counter_data.multisig(),
+        authority_account,
+        accounts,
+    )?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    // Shard increments leave the counter untouched, so they could not be rate limited
+    if counter_data.rate_limit().is_some() {
+        msg!("Rate limited counters cannot be sharded");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Shards are added in order, so the counter knows all of them by their number
+    if shard != counter_data.shards || shard >= MAX_SHARDS {
+        msg!(
+            "Expected shard {} of at most {}",
+            counter_data.shards,
+            MAX_SHARDS
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Verify the shard address is derived from the counter and the shard number
+    let (expected_address, bump) = find_shard_address(program_id, counter_account.key, shard);
+    if shard_account.key != &expected_address {
+        msg!("Shard address does not match its seeds");
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Create the shard account, signing for its address with the seeds
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer_account.key,
+            shard_account.key,
+            rent.minimum_balance(ShardAccount::LEN),
+            ShardAccount::LEN as u64,
+            program_id,
+        ),
+        &[
+            payer_account.clone(),
+            shard_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[SHARD_SEED, counter_account.key.as_ref(), &[shard], &[bump]]],
+    )?;
+
+    let shard_data = ShardAccount::new(*counter_account.key, shard, bump);
+    shard_data.serialize(&mut &mut shard_account.data.borrow_mut()[..])?;
+
+    counter_data.shards += 1;
+    counter_data.serialize(&mut &mut counter_account.data.borrow_mut()[..])?;
+
+    msg!("Counter shard {} initialized", shard);
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Fold the pending increments of the shards following the config account into the
+// counter. Anyone may aggregate, the increments were authorized when they were made.
+fn process_aggregate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let _config_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut counter_data = CounterAccount::unpack(&counter_account.data.borrow())?;
+    check_not_frozen(counter_data.is_frozen())?;
+
+    // Each shard's increments were checked against the count alone, so together they
+    // can go past max. They were accepted already, and refusing them here would leave
+    // the shards stuck, so under the error policy the count stops at max instead.
+    let bounds = counter_data.bounds();
+    for shard_account in accounts_iter {
+        if shard_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut data = shard_account.data.borrow_mut();
+        let mut shard_data = ShardAccount::unpack(&data)?;
+        if shard_data.counter != *counter_account.key {
+            msg!("Shard belongs to another counter");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if shard_data.pending == 0 {
+            continue;
+        }
+        let previous = counter_data.count;
+        counter_data.count = bounds
+            .increment(counter_data.count, shard_data.pending)
+            .unwrap_or(bounds.max);
+        shard_data.pending = 0;
+        shard_data.serialize(&mut &mut data[..])?;
+
+        // Shards do not keep who incremented them, so each shard's increments are
+        // recorded as one change signed by the shard
+        counter_data.record_change(*shard_account.key, previous)?;
+    }
+    counter_data.serialize(&mut &mut counter_account.data.borrow_mut()[..])?;
+
+    msg!("Counter aggregated to: {}", counter_data.count);
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Rewrite a counter in place with the current account layout
+fn process_migrate_counter(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Verify system program
+    if system_program.key != &solana_program::system_program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let counter_data = {
+        let data = counter_account.data.borrow();
+        if data.len() == CounterAccount::LEGACY_LEN {
+            // Legacy counters have no authority, so the counter keypair itself
+            // signs to hand the counter over to the new authority
+            if !counter_account.is_signer || !authority_account.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            let count = unpack_u64(&data).map_err(|_| ProgramError::InvalidAccountData)?;
+
+            // Legacy counters live at keypair addresses, so there is no bump
+            CounterAccount::new(count, *authority_account.key, 0, CounterBounds::default())
+        } else {
+            // Versioned counters keep their authority, so anyone may upgrade them
+            CounterAccount::unpack_outdated(&data)?
+        }
+    };
+
+    // Top up the rent for the larger account
+    let rent = Rent::get()?;
+    let missing_lamports = rent
+        .minimum_balance(CounterAccount::LEN)
+        .saturating_sub(counter_account.lamports());
+    if missing_lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer_account.key, counter_account.key, missing_lamports),
+            &[
+                payer_account.clone(),
+                counter_account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+    }
+
+    // Grow the account and write the new layout
+    counter_account.resize(CounterAccount::LEN)?;
+    counter_data.serialize(&mut &mut counter_account.data.borrow_mut()[..])?;
+
+    msg!(
+        "Counter migrated to version {} with value: {}",
+        counter_data.version,
+        counter_data.count
+    );
+    return_count(counter_data.count);
+    Ok(())
+}
+
+// Seed of the program config address
+pub const CONFIG_SEED: &[u8] = b"config";
+
+// Derive the address of the program config
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+}
+
+// Owner of the accounts holding the upgrade authority of upgradeable programs
+pub const BPF_LOADER_UPGRADEABLE_ID: Pubkey =
+    solana_program::pubkey!("BPFLoaderUpgradeab1e11111111111111111111111");
+
+// Derive the address of the upgradeable loader's program data account of a program
+pub fn find_program_data_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[program_id.as_ref()], &BPF_LOADER_UPGRADEABLE_ID)
+}
+
+// Read the program's upgrade authority from its program data account, `None` once the
+// program is immutable. The loader's bincode layout is decoded by hand: a u32 tag of 3,
+// the u64 slot of the last deploy, then the authority as an option.
+fn upgrade_authority(
+    program_id: &Pubkey,
+    program_data_account: &AccountInfo,
+) -> Result<Option<Pubkey>, ProgramError> {
+    let (program_data_address, _bump) = find_program_data_address(program_id);
+    if program_data_account.key != &program_data_address {
+        msg!("Program data address does not match its seeds");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if program_data_account.owner != &BPF_LOADER_UPGRADEABLE_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let data = program_data_account.try_borrow_data()?;
+    if data.len() < PROGRAM_DATA_METADATA_LEN || data[..4] != 3u32.to_le_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    match data[12] {
+        0 => Ok(None),
+        1 => Ok(Some(Pubkey::try_from(&data[13..45]).unwrap())),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+// Size of the program data account's header, before the program itself
+const PROGRAM_DATA_METADATA_LEN: usize = 4 + 8 + 1 + 32;
+
+// Read the program config from the account at the instruction's config position
+fn load_config(
+    program_id: &Pubkey,
+    config_account: Option<&AccountInfo>,
+) -> Result<ProgramConfig, ProgramError> {
+    let config_account = config_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let (config_address, _bump) = find_config_address(program_id);
+    if config_account.key != &config_address {
+        msg!("Config address does not match its seeds");
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Until the admin creates the config, the program runs unpaused with a step of 1
+    if config_account.owner != program_id {
+        return Ok(ProgramConfig::new(Pubkey::default(), 1, 0));
+    }
+    ProgramConfig::unpack(&config_account.data.borrow())
+}
+
+// Create the program config, making the program's upgrade authority its admin
+fn process_initialize_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    default_step: u64,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let config_account = next_account_info(accounts_iter)?;
+    let admin_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let program_data_account = next_account_info(accounts_iter)?;
+
+    // Verify system program
+    if system_program.key != &solana_program::system_program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if !admin_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // Whoever created the config could pause every counter for good, so it is reserved
+    // to whoever controls the program's deploys
+    if upgrade_authority(program_id, program_data_account)? != Some(*admin_account.key) {
+        msg!("Only the program's upgrade authority can create the config");
+        return Err(CounterError::Unauthorized.into());
+    }
+
+    let (config_address, bump) = find_config_address(program_id);
+    if config_account.key != &config_address {
+        msg!("Config address does not match its seeds");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if config_account.owner == program_id || !config_account.data_is_empty() {
+        return Err(CounterError::AlreadyInitialized.into());
+    }
+    if default_step == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            admin_account.key,
+            config_account.key,
+            rent.minimum_balance(ProgramConfig::LEN),
+            ProgramConfig::LEN as u64,
+            program_id,
+        ),
+        &[
+            admin_account.clone(),
+            config_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[CONFIG_SEED, &[bump]]],
+    )?;
+
+    let config = ProgramConfig::new(*admin_account.key, default_step, bump);
+    config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+
+    msg!(
+        "Program config initialized with admin: {}",
+        admin_account.key
+    );
+    Ok(())
+}
+
+// Turn the global pause on or off
+fn process_set_paused(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    update_config(program_id, accounts, |config| config.paused = paused)?;
+
+    if paused {
+        msg!("Counter program paused");
+    } else {
+        msg!("Counter program unpaused");
+    }
+    Ok(())
+}
+
+// Change the admin and the default step of the program config
+fn process_update_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_admin: Pubkey,
+    default_step: u64,
+) -> ProgramResult {
+    if default_step == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    update_config(program_id, accounts, |config| {
+        config.admin = new_admin;
+        config.default_step = default_step;
+    })?;
+
+    msg!(
+        "Program config updated, admin: {}, default step: {}",
+        new_admin,
+        default_step
+    );
+    Ok(())
+}
+
+// Load the program config, check the admin signed, apply `update` and store the result
+fn update_config<F>(program_id: &Pubkey, accounts: &[AccountInfo], update: F) -> ProgramResult
+where
+    F: FnOnce(&mut ProgramConfig),
+{
+    let accounts_iter = &mut accounts.iter();
+    let config_account = next_account_info(accounts_iter)?;
+    let admin_account = next_account_info(accounts_iter)?;
+
+    // Verify account ownership
+    if config_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut data = config_account.data.borrow_mut();
+    let mut config = ProgramConfig::unpack(&data)?;
+
+    if config.admin != *admin_account.key || !admin_account.is_signer {
+        return Err(CounterError::Unauthorized.into());
+    }
+
+    update(&mut config);
+    config.serialize(&mut &mut data[..])?;
+    Ok(())
+}
+
+// Fail if the counter is frozen
+fn check_not_frozen(frozen: bool) -> ProgramResult {
+    if frozen {
+        return Err(CounterError::Frozen.into());
+    }
+    Ok(())
+}
+
+// Make sure the counter's authority signed the transaction. That is the given account
+// being its authority key, or for a counter with a multisig, at least the threshold of
+// its signers among the signers of the instruction's accounts.
+fn check_authority(
+    authority: &Pubkey,
+    multisig: Option<Multisig>,
+    authority_account: &AccountInfo,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if *authority == Pubkey::default() {
+        msg!("Counter authority has been renounced");
+        return Err(CounterError::Unauthorized.into());
+    }
+    if let Some(multisig) = multisig {
+        // The authority account is recorded as the one who signed, so it has to be one of
+        // the signers counted
+        if !authority_account.is_signer || !multisig.signers.contains(authority_account.key) {
+            msg!("The authority account has to be a signer of the counter's multisig");
+            return Err(CounterError::Unauthorized.into());
+        }
+        // Registered signers are distinct, so each one counts once however often it
+        // appears in the accounts
+        let signed = multisig
+            .signers
+            .iter()
+            .filter(|signer| {
+                accounts
+                    .iter()
+                    .any(|account| account.key == *signer && account.is_signer)
+            })
+            .count();
+        if signed < multisig.threshold as usize {
+            msg!(
+                "{} of the counter's signers signed, {} have to",
+                signed,
+                multisig.threshold
+            );
+            return Err(CounterError::Unauthorized.into());
+        }
+        return Ok(());
+    }
+    if authority != authority_account.key || !authority_account.is_signer {
+        return Err(CounterError::Unauthorized.into());
+    }
+    Ok(())
+}
+
+// Make sure the counter's authority, or a delegate with a grant left, signed an
+// increment. The delegate's record is looked for among the accounts, and the increment
+// is taken out of its allowance.
+fn check_increment_authority(
+    program_id: &Pubkey,
+    counter: &Pubkey,
+    generation: u64,
+    authority: &Pubkey,
+    multisig: Option<Multisig>,
+    authority_account: &AccountInfo,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let Err(error) = check_authority(authority, multisig, authority_account, accounts) else {
+        return Ok(());
+    };
+    // Delegates act for the authority, so renouncing it ends their grants too
+    if *authority == Pubkey::default() || !authority_account.is_signer {
+        return Err(error);
+    }
+    for account in accounts {
+        // Accounts already borrowed, like the counter being incremented, are not records
+        let is_delegate = account.owner == program_id
+            && account
+                .try_borrow_data()
+                .is_ok_and(|data| data.starts_with(&DelegateAccount::DISCRIMINATOR));
+        if !is_delegate {
+            continue;
+        }
+        let mut data = account.try_borrow_mut_data()?;
+        let mut delegate_data = DelegateAccount::unpack(&data)?;
+        // Grants made on an earlier counter at this address are void
+        if delegate_data.counter != *counter
+            || delegate_data.generation != generation
+            || delegate_data.delegate != *authority_account.key
+        {
+            continue;
+        }
+        delegate_data.use_allowance(&Clock::get()?)?;
+        delegate_data.serialize(&mut &mut data[..])?;
+        return Ok(());
+    }
+    Err(error)
+}
+
+// Make sure a gated counter's incrementing signer presented a token account among the
+// accounts that it owns and that holds at least the gate's balance of its mint
+fn check_token_gate(
+    gate: Option<TokenGate>,
+    signer_account: &AccountInfo,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let Some(gate) = gate else {
+        return Ok(());
+    };
+    // The counter itself is already borrowed, but it is no token account either
+    let holds = signer_account.is_signer
+        && accounts.iter().any(|account| {
+            TokenAccount::is_token_program(account.owner)
+                && account.try_borrow_data().is_ok_and(|data| {
+                    TokenAccount::unpack(&data).is_ok_and(|token| {
+                        token.mint == gate.mint
+                            && token.owner == *signer_account.key
+                            && token.amount >= gate.min_balance
+                    })
+                })
+        });
+    if !holds {
+        msg!(
+            "Signer has to hold at least {} of mint: {}",
+            gate.min_balance,
+            gate.mint
+        );
+        return Err(CounterError::TokenGated.into());
+    }
+    Ok(())
+}
+
+// Struct representing our counter account's data
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct CounterAccount {
+    discriminator: [u8; 8],
+    version: u8,
+    count: u64,
+    authority: Pubkey,
+    bump: u8,
+    // Added in version 2
+    min: u64,
+    max: u64,
+    policy: OverflowPolicy,
+    // Added in version 3
+    frozen: bool,
+    // Added in version 4
+    shards: u8,
+    // Added in version 5
+    changes: u64,
+    history: [HistoryEntry; HISTORY_LEN],
+    // Added in version 6
+    rate_limit_max: u64,
+    rate_limit_window: u64,
+    rate_limit_unit: WindowUnit,
+    window_start: u64,
+    window_increments: u64,
+    // Added in version 7
+    shared: bool,
+    // Added in version 8
+    fee_price: u64,
+    treasury: Pubkey,
+    // Added in version 9
+    threshold: u8,
+    signer_count: u8,
+    signers: [Pubkey; MAX_SIGNERS],
+    // Added in version 10
+    gate_mint: Pubkey,
+    gate_min_balance: u64,
+    // Added in version 11
+    generation: u64,
+}
+
+impl CounterAccount {
+    // Tag at the start of every counter account
+    pub const DISCRIMINATOR: [u8; 8] = *b"COUNTER\0";
+
+    // Layout version written by this program
+    pub const VERSION: u8 = 11;
+
+    // Size in bytes: discriminator, version, u64 count, authority pubkey, address bump,
+    // u64 minimum, u64 maximum, overflow policy, frozen flag, number of shards, u64
+    // number of changes, the history entries, u64 increments and u64 length of the rate
+    // limit window, window unit, u64 start of the current window, u64 increments made in
+    // it, shared flag, u64 fee price, treasury pubkey, multisig threshold, number of
+    // signers, the signer pubkeys, gate mint pubkey, u64 gate balance and u64 generation
+    pub const LEN: usize = Self::V10_LEN + 8;
+
+    // Size of the version 10 layout, the last one without a generation
+    const V10_LEN: usize = Self::V9_LEN + 32 + 8;
+
+    // Size of the version 9 layout, the last one without a token gate
+    const V9_LEN: usize = Self::V8_LEN + 1 + 1 + MAX_SIGNERS * 32;
+
+    // Size of the version 8 layout, the last one without a multisig
+    const V8_LEN: usize = Self::V5_LEN + 8 + 8 + 1 + 8 + 8 + 1 + 8 + 32;
+
+    // Size of the version 5 layout, the last one without a rate limit
+    const V5_LEN: usize =
+        8 + 1 + 8 + 32 + 1 + 8 + 8 + 1 + 1 + 1 + 8 + HISTORY_LEN * HistoryEntry::LEN;
+
+    // Size of the original layout, a bare u64 count
+    pub const LEGACY_LEN: usize = 8;
+
+    pub fn new(count: u64, authority: Pubkey, bump: u8, bounds: CounterBounds) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            count,
+            authority,
+            bump,
+            min: bounds.min,
+            max: bounds.max,
+            policy: bounds.policy,
+            frozen: false,
+            shards: 0,
+            changes: 0,
+            history: [HistoryEntry::default(); HISTORY_LEN],
+            rate_limit_max: 0,
+            rate_limit_window: 0,
+            rate_limit_unit: WindowUnit::Slots,
+            window_start: 0,
+            window_increments: 0,
+            shared: false,
+            fee_price: 0,
+            treasury: Pubkey::default(),
+            threshold: 0,
+            signer_count: 0,
+            signers: [Pubkey::default(); MAX_SIGNERS],
+            gate_mint: Pubkey::default(),
+            gate_min_balance: 0,
+            generation: 0,
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    // Number handed out to the counter when it was created, no two counters get the
+    // same one. 0 for counters migrated from before version 11. Records of its
+    // contributors and delegates tell it apart from other counters that lived at the
+    // same address.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // The default pubkey once the authority has been renounced
+    pub fn authority(&self) -> &Pubkey {
+        &self.authority
+    }
+
+    pub fn bump(&self) -> u8 {
+        self.bump
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    // Number of shards, 0 unless the counter is sharded
+    pub fn shards(&self) -> u8 {
+        self.shards
+    }
+
+    pub fn bounds(&self) -> CounterBounds {
+        CounterBounds {
+            min: self.min,
+            max: self.max,
+            policy: self.policy,
+        }
+    }
+
+    // Whether anyone may contribute to the counter
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
+    // `None` unless increments cost a fee
+    pub fn fee(&self) -> Option<CounterFee> {
+        (self.fee_price > 0).then_some(CounterFee {
+            price: self.fee_price,
+            treasury: self.treasury,
+        })
+    }
+
+    // Registered signers, which only stand in for the authority once there is a threshold
+    pub fn signers(&self) -> &[Pubkey] {
+        &self.signers[..self.signer_count as usize]
+    }
+
+    // `None` unless the counter's authority is a multisig
+    pub fn multisig(&self) -> Option<Multisig<'_>> {
+        (self.threshold > 0).then_some(Multisig {
+            threshold: self.threshold,
+            signers: self.signers(),
+        })
+    }
+
+    // `None` unless only token holders may increment the counter
+    pub fn token_gate(&self) -> Option<TokenGate> {
+        (self.gate_mint != Pubkey::default()).then_some(TokenGate {
+            mint: self.gate_mint,
+            min_balance: self.gate_min_balance,
+        })
+    }
+
+    // `None` unless the counter is rate limited
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        (self.rate_limit_window > 0).then_some(RateLimit {
+            max_increments: self.rate_limit_max,
+            window: self.rate_limit_window,
+            unit: self.rate_limit_unit,
+        })
+    }
+
+    // Count an increment against the rate limit, moving on to a new window once the
+    // current one has passed. Fails once the window's increments are used up.
+    fn use_rate_limit(&mut self) -> ProgramResult {
+        let Some(rate_limit) = self.rate_limit() else {
+            return Ok(());
+        };
+        (self.window_start, self.window_increments) =
+            rate_limit.use_window(&Clock::get()?, self.window_start, self.window_increments)?;
+        Ok(())
+    }
+
+    // Number of changes made to the counter since it was created or migrated, including
+    // the ones that have dropped out of the history
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    // The last `HISTORY_LEN` changes, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &HistoryEntry> {
+        let len = self.changes.min(HISTORY_LEN as u64) as usize;
+        let start = (self.changes % HISTORY_LEN as u64) as usize + HISTORY_LEN - len;
+        (start..start + len).map(move |i| &self.history[i % HISTORY_LEN])
+    }
+
+    // Write a change from `previous` to the current count into the history, replacing
+    // the oldest entry once it is full
+    fn record_change(&mut self, signer: Pubkey, previous: u64) -> ProgramResult {
+        self.history[(self.changes % HISTORY_LEN as u64) as usize] =
+            HistoryEntry::new(&Clock::get()?, signer, previous, self.count);
+        // 2^64 is a multiple of HISTORY_LEN, so wrapping keeps the entries in order
+        self.changes = self.changes.wrapping_add(1);
+        Ok(())
+    }
+
+    // Every counter this program writes has min <= count <= max, which the bounds
+    // arithmetic relies on, and no more signers than fit or are needed
+    fn is_consistent(&self) -> bool {
+        self.min <= self.max
+            && (self.min..=self.max).contains(&self.count)
+            && self.signer_count as usize <= MAX_SIGNERS
+            && self.threshold <= self.signer_count
+    }
+
+    // Size of the account data written by each layout version
+    fn versioned_len(version: u8) -> Option<usize> {
+        match version {
+            1 => Some(8 + 1 + 8 + 32 + 1),
+            2 => Some(8 + 1 + 8 + 32 + 1 + 8 + 8 + 1),
+            3 => Some(8 + 1 + 8 + 32 + 1 + 8 + 8 + 1 + 1),
+            4 => Some(8 + 1 + 8 + 32 + 1 + 8 + 8 + 1 + 1 + 1),
+            5 => Some(Self::V5_LEN),
+            6 => Some(Self::V5_LEN + 8 + 8 + 1 + 8 + 8),
+            7 => Some(Self::V5_LEN + 8 + 8 + 1 + 8 + 8 + 1),
+            8 => Some(Self::V8_LEN),
+            9 => Some(Self::V9_LEN),
+            10 => Some(Self::V10_LEN),
+            11 => Some(Self::LEN),
+            _ => None,
+        }
+    }
+
+    // Deserialize a counter written by an earlier layout version and upgrade it to the
+    // current one. Fields are only ever appended, so older data is zero padded and the
+    // fields that did not exist yet get their defaults.
+    pub fn unpack_outdated(data: &[u8]) -> Result<Self, ProgramError> {
+        let version = *data
+            .get(Self::DISCRIMINATOR.len())
+            .ok_or(CounterError::WrongAccountSize)?;
+        if Self::versioned_len(version) != Some(data.len()) {
+            return Err(CounterError::WrongAccountSize.into());
+        }
+
+        let mut padded = data.to_vec();
+        padded.resize(Self::LEN, 0);
+        let mut counter_data =
+            Self::try_from_slice(&padded).map_err(|_| ProgramError::InvalidAccountData)?;
+        if counter_data.discriminator != Self::DISCRIMINATOR {
+            return Err(CounterError::InvalidAccountType.into());
+        }
+
+        // Version 2 added bounds, older counters are unbounded
+        if version < 2 {
+            counter_data.max = u64::MAX;
+        }
+
+        counter_data.version = Self::VERSION;
+        if !counter_data.is_consistent() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(counter_data)
+    }
+
+    // Fail unless `data` has the size of a current counter account
+    fn check_len(data: &[u8]) -> ProgramResult {
+        if data.len() != Self::LEN {
+            // Accounts written by an older layout have to be migrated first
+            let outdated = data.len() == Self::LEGACY_LEN
+                || data
+                    .get(Self::DISCRIMINATOR.len())
+                    .and_then(|&version| Self::versioned_len(version))
+                    == Some(data.len());
+            return Err(if outdated {
+                CounterError::OutdatedVersion
+            } else {
+                CounterError::WrongAccountSize
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    // Deserialize a counter, rejecting anything that is not a current counter account
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::check_len(data)?;
+        let counter_data =
+            Self::try_from_slice(data).map_err(|_| ProgramError::InvalidAccountData)?;
+        if counter_data.discriminator != Self::DISCRIMINATOR {
+            return Err(CounterError::InvalidAccountType.into());
+        }
+        if counter_data.version != Self::VERSION {
+            return Err(CounterError::OutdatedVersion.into());
+        }
+        if !counter_data.is_consistent() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(counter_data)
+    }
+}
+
+// Fixed layout view of a current counter account, read and updated in place without a
+// Borsh round trip. The fields are those of `CounterAccount` byte for byte, with
+// integers kept as little-endian byte arrays so nothing needs aligning, and enums and
+// flags as their Borsh tag bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct PodCounterAccount {
+    discriminator: [u8; 8],
+    version: u8,
+    count: [u8; 8],
+    authority: Pubkey,
+    bump: u8,
+    min: [u8; 8],
+    max: [u8; 8],
+    policy: u8,
+    frozen: u8,
+    shards: u8,
+    changes: [u8; 8],
+    history: [[u8; HistoryEntry::LEN]; HISTORY_LEN],
+    rate_limit_max: [u8; 8],
+    rate_limit_window: [u8; 8],
+    rate_limit_unit: u8,
+    window_start: [u8; 8],
+    window_increments: [u8; 8],
+    shared: u8,
+    fee_price: [u8; 8],
+    treasury: Pubkey,
+    threshold: u8,
+    signer_count: u8,
+    signers: [Pubkey; MAX_SIGNERS],
+    gate_mint: Pubkey,
+    gate_min_balance: [u8; 8],
+    generation: [u8; 8],
+}
+
+const _: () = assert!(std::mem::size_of::<PodCounterAccount>() == CounterAccount::LEN);
+
+impl PodCounterAccount {
+    // View a counter in place, rejecting exactly what `CounterAccount::unpack` rejects
+    pub fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        CounterAccount::check_len(data)?;
+        let counter_data: &Self = bytemuck::from_bytes(data);
+        counter_data.check()?;
+        Ok(counter_data)
+    }
+
+    // Mutable version of `load`
+    pub fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        CounterAccount::check_len(data)?;
+        let counter_data: &mut Self = bytemuck::from_bytes_mut(data);
+        counter_data.check()?;
+        Ok(counter_data)
+    }
+
+    fn check(&self) -> ProgramResult {
+        // Bytes Borsh would refuse to decode as an overflow policy, window unit or bool
+        if self.policy > OverflowPolicy::Wrap as u8
+            || self.rate_limit_unit > WindowUnit::Seconds as u8
+            || self.frozen > 1
+            || self.shared > 1
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if self.discriminator != CounterAccount::DISCRIMINATOR {
+            return Err(CounterError::InvalidAccountType.into());
+        }
+        if self.version != CounterAccount::VERSION {
+            return Err(CounterError::OutdatedVersion.into());
+        }
+        let bounds = self.bounds();
+        if bounds.min > bounds.max
+            || !(bounds.min..=bounds.max).contains(&self.count())
+            || self.signer_count as usize > MAX_SIGNERS
+            || self.threshold > self.signer_count
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    pub fn count(&self) -> u64 {
+        u64::from_le_bytes(self.count)
+    }
+
+    pub fn generation(&self) -> u64 {
+        u64::from_le_bytes(self.generation)
+    }
+
+    fn set_count(&mut self, count: u64) {
+        self.count = count.to_le_bytes();
+    }
+
+    // The default pubkey once the authority has been renounced
+    pub fn authority(&self) -> &Pubkey {
+        &self.authority
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen != 0
+    }
+
+    pub fn bounds(&self) -> CounterBounds {
+        CounterBounds {
+            min: u64::from_le_bytes(self.min),
+            max: u64::from_le_bytes(self.max),
+            policy: match self.policy {
+                0 => OverflowPolicy::Error,
+                1 => OverflowPolicy::Saturate,
+                _ => OverflowPolicy::Wrap,
+            },
+        }
+    }
+
+    // `None` unless the counter's authority is a multisig
+    pub fn multisig(&self) -> Option<Multisig<'_>> {
+        (self.threshold > 0).then_some(Multisig {
+            threshold: self.threshold,
+            signers: &self.signers[..self.signer_count as usize],
+        })
+    }
+
+    // `None` unless only token holders may increment the counter
+    pub fn token_gate(&self) -> Option<TokenGate> {
+        (self.gate_mint != Pubkey::default()).then_some(TokenGate {
+            mint: self.gate_mint,
+            min_balance: u64::from_le_bytes(self.gate_min_balance),
+        })
+    }
+
+    // `None` unless increments cost a fee
+    pub fn fee(&self) -> Option<CounterFee> {
+        let price = u64::from_le_bytes(self.fee_price);
+        (price > 0).then_some(CounterFee {
+            price,
+            treasury: self.treasury,
+        })
+    }
+
+    // `None` unless the counter is rate limited
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        let window = u64::from_le_bytes(self.rate_limit_window);
+        (window > 0).then_some(RateLimit {
+            max_increments: u64::from_le_bytes(self.rate_limit_max),
+            window,
+            unit: match self.rate_limit_unit {
+                0 => WindowUnit::Slots,
+                _ => WindowUnit::Seconds,
+            },
+        })
+    }
+
+    // Same as `CounterAccount::use_rate_limit`
+    fn use_rate_limit(&mut self) -> ProgramResult {
+        let Some(rate_limit) = self.rate_limit() else {
+            return Ok(());
+        };
+        let (window_start, window_increments) = rate_limit.use_window(
+            &Clock::get()?,
+            u64::from_le_bytes(self.window_start),
+            u64::from_le_bytes(self.window_increments),
+        )?;
+        self.window_start = window_start.to_le_bytes();
+        self.window_increments = window_increments.to_le_bytes();
+        Ok(())
+    }
+
+    // Same as `CounterAccount::record_change`
+    fn record_change(&mut self, signer: Pubkey, previous: u64) -> ProgramResult {
+        let changes = u64::from_le_bytes(self.changes);
+        let entry = HistoryEntry::new(&Clock::get()?, signer, previous, self.count());
+        entry.serialize(&mut &mut self.history[(changes % HISTORY_LEN as u64) as usize][..])?;
+        self.changes = changes.wrapping_add(1).to_le_bytes();
+        Ok(())
+    }
+}
+
+// The fields of an SPL Token or Token-2022 token account a token gate checks, decoded
+// from the token program's account layout: mint, owner and u64 amount first, the
+// account state at byte 108 and, for Token-2022 accounts with extensions, the account
+// type right after the 165 base bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+impl TokenAccount {
+    // Size of a token account without extensions
+    pub const LEN: usize = 165;
+
+    // Size of a token program multisig, which Token-2022 tells apart from extended
+    // accounts by size alone
+    const MULTISIG_LEN: usize = 355;
+
+    // Whether `program_id` is one of the token programs whose accounts a gate accepts
+    pub fn is_token_program(program_id: &Pubkey) -> bool {
+        *program_id == TOKEN_PROGRAM_ID || *program_id == TOKEN_2022_PROGRAM_ID
+    }
+
+    // Decode an initialized or frozen token account, rejecting mints, multisigs and
+    // anything else. Does not check which program owns the data.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let is_account = data.len() == Self::LEN
+            || (data.len() > Self::LEN
+                && data.len() != Self::MULTISIG_LEN
+                && data[Self::LEN] == TOKEN_ACCOUNT_TYPE);
+        // Initialized or frozen, frozen accounts still hold their tokens
+        if !is_account || !matches!(data[108], 1 | 2) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Self {
+            mint: Pubkey::try_from(&data[..32]).unwrap(),
+            owner: Pubkey::try_from(&data[32..64]).unwrap(),
+            amount: u64::from_le_bytes(data[64..72].try_into().unwrap()),
+        })
+    }
+}
+
+// Owners of the token accounts a token gate accepts
+pub const TOKEN_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const TOKEN_2022_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+// Token-2022 account type of token accounts, after the base layout of extended accounts
+const TOKEN_ACCOUNT_TYPE: u8 = 2;
+
+// Number of changes a counter keeps in its history
+pub const HISTORY_LEN: usize = 8;
+
+// One change of a counter's value, as kept in its history
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HistoryEntry {
+    slot: u64,
+    unix_timestamp: i64,
+    signer: Pubkey,
+    delta: i128,
+    value: u64,
+}
+
+impl HistoryEntry {
+    // Size in bytes: u64 slot, i64 unix timestamp, signer pubkey, i128 delta and u64
+    // resulting value
+    pub const LEN: usize = 8 + 8 + 32 + 16 + 8;
+
+    // A change from `previous` to `value` made by `signer` at `clock`
+    fn new(clock: &Clock, signer: Pubkey, previous: u64, value: u64) -> Self {
+        Self {
+            slot: clock.slot,
+            unix_timestamp: clock.unix_timestamp,
+            signer,
+            delta: value as i128 - previous as i128,
+            value,
+        }
+    }
+
+    pub fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    pub fn unix_timestamp(&self) -> i64 {
+        self.unix_timestamp
+    }
+
+    // The authority that made the change, or the shard whose increments were aggregated
+    pub fn signer(&self) -> &Pubkey {
+        &self.signer
+    }
+
+    // Difference between the resulting value and the one before. Wrapping or saturating
+    // counters can move by a different amount than was asked for.
+    pub fn delta(&self) -> i128 {
+        self.delta
+    }
+
+    // Count after the change
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+}
+
+// Struct representing what one user contributed to a counter, stored at the
+// contribution address. Only contributions to the counter's current generation count,
+// a record left from a closed counter starts over.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ContributionAccount {
+    discriminator: [u8; 8],
+    version: u8,
+    counter: Pubkey,
+    generation: u64,
+    contributor: Pubkey,
+    bump: u8,
+    total: u64,
+    contributions: u64,
+}
+
+impl ContributionAccount {
+    // Tag at the start of every contribution account
+    pub const DISCRIMINATOR: [u8; 8] = *b"CONTRIB\0";
+
+    // Layout version written by this program
+    pub const VERSION: u8 = 1;
+
+    // Size in bytes: discriminator, version, counter pubkey, u64 counter generation,
+    // contributor pubkey, address bump, u64 total amount and u64 number of contributions
+    pub const LEN: usize = 8 + 1 + 32 + 8 + 32 + 1 + 8 + 8;
+
+    pub fn new(counter: Pubkey, generation: u64, contributor: Pubkey, bump: u8) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            counter,
+            generation,
+            contributor,
+            bump,
+            total: 0,
+            contributions: 0,
+        }
+    }
+
+    pub fn counter(&self) -> &Pubkey {
+        &self.counter
+    }
+
+    // Generation of the counter the contributions were made to
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn contributor(&self) -> &Pubkey {
+        &self.contributor
+    }
+
+    // Sum of the amounts contributed. Saturating or wrapping counters may have moved by
+    // less.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    // Number of contributions made
+    pub fn contributions(&self) -> u64 {
+        self.contributions
+    }
+
+    // Deserialize a contribution, rejecting anything that is not a contribution account
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() != Self::LEN {
+            return Err(CounterError::WrongAccountSize.into());
+        }
+        let contribution_data =
+            Self::try_from_slice(data).map_err(|_| ProgramError::InvalidAccountData)?;
+        if contribution_data.discriminator != Self::DISCRIMINATOR {
+            return Err(CounterError::InvalidAccountType.into());
+        }
+        if contribution_data.version != Self::VERSION {
+            return Err(CounterError::OutdatedVersion.into());
+        }
+        Ok(contribution_data)
+    }
+}
+
+// Struct representing what a delegate may still add to a counter, stored at the
+// delegate address
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct DelegateAccount {
+    discriminator: [u8; 8],
+    version: u8,
+    counter: Pubkey,
+    generation: u64,
+    delegate: Pubkey,
+    payer: Pubkey,
+    bump: u8,
+    allowance: u64,
+    expires_at: i64,
+}
+
+impl DelegateAccount {
+    // Tag at the start of every delegate account
+    pub const DISCRIMINATOR: [u8; 8] = *b"DELEGATE";
+
+    // Layout version written by this program
+    pub const VERSION: u8 = 1;
+
+    // Size in bytes: discriminator, version, counter pubkey, u64 counter generation,
+    // delegate pubkey, payer pubkey, address bump, u64 increments left and i64 expiry
+    pub const LEN: usize = 8 + 1 + 32 + 8 + 32 + 32 + 1 + 8 + 8;
+
+    // No allowance or expiry is stored as the largest value, which no counter or clock
+    // reaches
+    pub fn new(
+        counter: Pubkey,
+        generation: u64,
+        delegate: Pubkey,
+        payer: Pubkey,
+        bump: u8,
+        allowance: Option<u64>,
+        expires_at: Option<i64>,
+    ) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            counter,
+            generation,
+            delegate,
+            payer,
+            bump,
+            allowance: allowance.unwrap_or(u64::MAX),
+            expires_at: expires_at.unwrap_or(i64::MAX),
+        }
+    }
+
+    pub fn counter(&self) -> &Pubkey {
+        &self.counter
+    }
+
+    // Generation of the counter the grant was made on
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn delegate(&self) -> &Pubkey {
+        &self.delegate
+    }
+
+    // Funded the record, and gets its rent back once the counter is gone
+    pub fn payer(&self) -> &Pubkey {
+        &self.payer
+    }
+
+    // How many more increments the delegate may make, `None` if it is unlimited
+    pub fn allowance(&self) -> Option<u64> {
+        (self.allowance != u64::MAX).then_some(self.allowance)
+    }
+
+    // Unix time from which the grant is no longer valid, `None` if it never expires
+    pub fn expires_at(&self) -> Option<i64> {
+        (self.expires_at != i64::MAX).then_some(self.expires_at)
+    }
+
+    // Take one increment made at `clock` out of the grant, however large it is
+    fn use_allowance(&mut self, clock: &Clock) -> ProgramResult {
+        if clock.unix_timestamp >= self.expires_at {
+            msg!("Delegate expired at {}", self.expires_at);
+            return Err(CounterError::DelegateExpired.into());
+        }
+        if let Some(allowance) = self.allowance() {
+            self.allowance = allowance.checked_sub(1).ok_or_else(|| {
+                msg!("Delegate has used up its allowance");
+                CounterError::AllowanceExceeded
+            })?;
+        }
+        Ok(())
+    }
+
+    // Deserialize a delegate, rejecting anything that is not a delegate account
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() != Self::LEN {
+            return Err(CounterError::WrongAccountSize.into());
+        }
+        let delegate_data =
+            Self::try_from_slice(data).map_err(|_| ProgramError::InvalidAccountData)?;
+        if delegate_data.discriminator != Self::DISCRIMINATOR {
+            return Err(CounterError::InvalidAccountType.into());
+        }
+        if delegate_data.version != Self::VERSION {
+            return Err(CounterError::OutdatedVersion.into());
+        }
+        Ok(delegate_data)
+    }
+}
+
+// Struct representing one shard of a sharded counter, stored at the shard address
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ShardAccount {
+    discriminator: [u8; 8],
+    version: u8,
+    counter: Pubkey,
+    shard: u8,
+    bump: u8,
+    pending: u64,
+}
+
+impl ShardAccount {
+    // Tag at the start of every shard account
+    pub const DISCRIMINATOR: [u8; 8] = *b"SHARD\0\0\0";
+
+    // Layout version written by this program
+    pub const VERSION: u8 = 1;
+
+    // Size in bytes: discriminator, version, counter pubkey, shard number, address bump
+    // and u64 pending increments
+    pub const LEN: usize = 8 + 1 + 32 + 1 + 1 + 8;
+
+    pub fn new(counter: Pubkey, shard: u8, bump: u8) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            counter,
+            shard,
+            bump,
+            pending: 0,
+        }
+    }
+
+    pub fn counter(&self) -> &Pubkey {
+        &self.counter
+    }
+
+    pub fn shard(&self) -> u8 {
+        self.shard
+    }
+
+    // Increments not yet aggregated into the counter
+    pub fn pending(&self) -> u64 {
+        self.pending
+    }
+
+    // Deserialize a shard, rejecting anything that is not a shard account
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() != Self::LEN {
+            return Err(CounterError::WrongAccountSize.into());
+        }
+        let shard_data =
+            Self::try_from_slice(data).map_err(|_| ProgramError::InvalidAccountData)?;
+        if shard_data.discriminator != Self::DISCRIMINATOR {
+            return Err(CounterError::InvalidAccountType.into());
+        }
+        if shard_data.version != Self::VERSION {
+            return Err(CounterError::OutdatedVersion.into());
+        }
+        Ok(shard_data)
+    }
+}
+
+// Struct representing the program-wide config, stored at the config address
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ProgramConfig {
+    discriminator: [u8; 8],
+    version: u8,
+    admin: Pubkey,
+    paused: bool,
+    default_step: u64,
+    bump: u8,
+}
+
+impl ProgramConfig {
+    // Tag at the start of the config account
+    pub const DISCRIMINATOR: [u8; 8] = *b"CONFIG\0\0";
+
+    // Layout version written by this program
+    pub const VERSION: u8 = 1;
+
+    // Size in bytes: discriminator, version, admin pubkey, paused flag, u64 default step
+    // and address bump
+    pub const LEN: usize = 8 + 1 + 32 + 1 + 8 + 1;
+
+    pub fn new(admin: Pubkey, default_step: u64, bump: u8) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            admin,
+            paused: false,
+            default_step,
+            bump,
+        }
+    }
+
+    pub fn admin(&self) -> &Pubkey {
+        &self.admin
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn default_step(&self) -> u64 {
+        self.default_step
+    }
+
+    // Deserialize the config, rejecting anything that is not a config account
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() != Self::LEN {
+            return Err(CounterError::WrongAccountSize.into());
+        }
+        let config = Self::try_from_slice(data).map_err(|_| ProgramError::InvalidAccountData)?;
+        if config.discriminator != Self::DISCRIMINATOR {
+            return Err(CounterError::InvalidAccountType.into());
+        }
+        if config.version != Self::VERSION {
+            return Err(CounterError::OutdatedVersion.into());
+        }
+        Ok(config)
+    }
+}
+
+// The last generation handed out to a counter, stored at the generation address
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct GenerationAccount {
+    discriminator: [u8; 8],
+    version: u8,
+    last: u64,
+    bump: u8,
+}
+
+impl GenerationAccount {
+    // Tag at the start of the generation account
+    pub const DISCRIMINATOR: [u8; 8] = *b"GENERATN";
+
+    // Layout version written by this program
+    pub const VERSION: u8 = 1;
+
+    // Size in bytes: discriminator, version, u64 last generation and address bump
+    pub const LEN: usize = 8 + 1 + 8 + 1;
+
+    pub fn new(bump: u8) -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
+            last: 0,
+            bump,
+        }
+    }
+
+    // Generation of the counter created last
+    pub fn last(&self) -> u64 {
+        self.last
+    }
+
+    // Deserialize the account, rejecting anything that is not the generation account
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() != Self::LEN {
+            return Err(CounterError::WrongAccountSize.into());
+        }
+        let generation_data =
+            Self::try_from_slice(data).map_err(|_| ProgramError::InvalidAccountData)?;
+        if generation_data.discriminator != Self::DISCRIMINATOR {
+            return Err(CounterError::InvalidAccountType.into());
+        }
+        if generation_data.version != Self::VERSION {
+            return Err(CounterError::OutdatedVersion.into());
+        }
+        Ok(generation_data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+    use solana_program_test::*;
+    use solana_sdk::{
+        account::Account,
+        hash::Hash,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        signature::{Keypair, Signer},
+        system_program,
+        transaction::{Transaction, TransactionError},
+    };
+
+    #[tokio::test]
+    async fn test_counter_program() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        // Derive the address of the payer's first counter
+        let (counter_address, bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+        let initial_value: u64 = 42;
+
+        // Step 1: Initialize the counter
+        println!("Testing counter initialization...");
+
+        // Create initialization instruction
+        let mut init_instruction_data = vec![0]; // 0 = initialize instruction
+        init_instruction_data.extend_from_slice(&initial_value.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes()); // counter index
+        init_instruction_data.push(0); // no bounds
+
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+
+        // Send transaction with initialize instruction
+        let mut transaction =
+            Transaction::new_with_payer(&[initialize_instruction], Some(&payer.pubkey()));
+        transaction.sign(&[&payer], recent_blockhash);
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        // Check account data
+        let account = banks_client
+            .get_account(counter_address)
+            .await
+            .expect("Failed to get counter account");
+
+        if let Some(account_data) = account {
+            let counter: CounterAccount = CounterAccount::try_from_slice(&account_data.data)
+                .expect("Failed to deserialize counter data");
+            assert_eq!(counter.count, 42);
+            assert_eq!(counter.authority, payer.pubkey());
+            assert_eq!(counter.bump, bump);
+            println!(
+                "✅ Counter initialized successfully with value: {}",
+                counter.count
+            );
+        }
+
+        // Step 2: Increment the counter
+        println!("Testing counter increment...");
+
+        // Create increment instruction
+        let increment_instruction = Instruction::new_with_bytes(
+            program_id,
+            &[1], // 1 = increment instruction
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new_readonly(payer.pubkey(), true), // payer is the authority
+                AccountMeta::new_readonly(config_address, false),
+            ],
+        );
+
+        // Send transaction with increment instruction
+        let mut transaction =
+            Transaction::new_with_payer(&[increment_instruction], Some(&payer.pubkey()));
+        transaction.sign(&[&payer], recent_blockhash);
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        // Check account data
+        let account = banks_client
+            .get_account(counter_address)
+            .await
+            .expect("Failed to get counter account");
+
+        if let Some(account_data) = account {
+            let counter: CounterAccount = CounterAccount::try_from_slice(&account_data.data)
+                .expect("Failed to deserialize counter data");
+            assert_eq!(counter.count, 43);
+            println!("✅ Counter incremented successfully to: {}", counter.count);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_counter_authority() {
+        let program_id = Pubkey::new_unique();
+        let (banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+        let new_authority = Keypair::new();
+        let stranger = Keypair::new();
+
+        // Initialize the counter, the payer becomes its authority
+        let mut init_instruction_data = vec![0];
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.push(0); // no bounds
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        let mut transaction =
+            Transaction::new_with_payer(&[initialize_instruction], Some(&payer.pubkey()));
+        transaction.sign(&[&payer], recent_blockhash);
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        let increment_instruction = |authority: Pubkey| {
+            Instruction::new_with_bytes(
+                program_id,
+                &[1],
+                vec![
+                    AccountMeta::new(counter_address, false),
+                    AccountMeta::new_readonly(authority, true),
+                    AccountMeta::new_readonly(config_address, false),
+                ],
+            )
+        };
+        let set_authority_instruction = |authority: Pubkey, new_authority: Option<Pubkey>| {
+            let mut data = vec![2];
+            data.extend_from_slice(&borsh::to_vec(&new_authority).unwrap());
+            Instruction::new_with_bytes(
+                program_id,
+                &data,
+                vec![
+                    AccountMeta::new(counter_address, false),
+                    AccountMeta::new_readonly(authority, true),
+                    AccountMeta::new_readonly(config_address, false),
+                ],
+            )
+        };
+
+        // Step 1: A stranger cannot increment the counter
+        println!("Testing increment by a stranger...");
+        let mut transaction = Transaction::new_with_payer(
+            &[increment_instruction(stranger.pubkey())],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &stranger], recent_blockhash);
+        let err = banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::Unauthorized as u32)
+            )
+        );
+
+        // Step 2: Hand the counter over to a new authority
+        println!("Testing authority transfer...");
+        let mut transaction = Transaction::new_with_payer(
+            &[set_authority_instruction(
+                payer.pubkey(),
+                Some(new_authority.pubkey()),
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        // The old authority is locked out, the new one can increment
+        let mut transaction = Transaction::new_with_payer(
+            &[increment_instruction(payer.pubkey())],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_err());
+
+        let mut transaction = Transaction::new_with_payer(
+            &[increment_instruction(new_authority.pubkey())],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &new_authority], recent_blockhash);
         banks_client.process_transaction(transaction).await.unwrap();
 
-        // Check account data
-        let account = banks_client
-            .get_account(counter_keypair.pubkey())
+        let account = banks_client
+            .get_account(counter_address)
+            .await
+            .expect("Failed to get counter account")
+            .expect("Counter account not found");
+        let counter = CounterAccount::try_from_slice(&account.data).unwrap();
+        assert_eq!(counter.count, 1);
+        assert_eq!(counter.authority, new_authority.pubkey());
+        println!("✅ Counter authority transferred to: {}", counter.authority);
+
+        // Step 3: Renounce the authority, nobody can change the counter anymore
+        println!("Testing authority renounce...");
+        let mut transaction = Transaction::new_with_payer(
+            &[set_authority_instruction(new_authority.pubkey(), None)],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &new_authority], recent_blockhash);
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        let account = banks_client
+            .get_account(counter_address)
+            .await
+            .expect("Failed to get counter account")
+            .expect("Counter account not found");
+        let counter = CounterAccount::try_from_slice(&account.data).unwrap();
+        assert_eq!(counter.authority, Pubkey::default());
+
+        // The former authority cannot take the counter back
+        let mut transaction = Transaction::new_with_payer(
+            &[set_authority_instruction(
+                new_authority.pubkey(),
+                Some(new_authority.pubkey()),
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &new_authority], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_err());
+        println!("✅ Counter authority renounced");
+    }
+
+    // Send `instruction` signed by the payer and any extra `signers`
+    async fn process(
+        banks_client: &mut BanksClient,
+        payer: &Keypair,
+        signers: &[&Keypair],
+        recent_blockhash: Hash,
+        instruction: Instruction,
+    ) -> Result<(), BanksClientError> {
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        let mut all_signers = vec![payer];
+        all_signers.extend_from_slice(signers);
+        transaction.sign(&all_signers, recent_blockhash);
+        banks_client.process_transaction(transaction).await
+    }
+
+    // The upgradeable loader's program data account of a program deployed by
+    // `upgrade_authority`, `None` for an immutable program
+    fn program_data_account(upgrade_authority: Option<Pubkey>) -> Account {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&0u64.to_le_bytes());
+        match upgrade_authority {
+            Some(upgrade_authority) => {
+                data.push(1);
+                data.extend_from_slice(upgrade_authority.as_ref());
+            }
+            None => data.push(0),
+        }
+        data.resize(PROGRAM_DATA_METADATA_LEN, 0);
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: BPF_LOADER_UPGRADEABLE_ID,
+            ..Account::default()
+        }
+    }
+
+    // Fetch and deserialize a counter account
+    async fn get_counter(banks_client: &mut BanksClient, address: Pubkey) -> CounterAccount {
+        let account = banks_client
+            .get_account(address)
+            .await
+            .expect("Failed to get counter account")
+            .expect("Counter account not found");
+        CounterAccount::try_from_slice(&account.data).expect("Failed to deserialize counter data")
+    }
+
+    #[tokio::test]
+    async fn test_counter_arithmetic() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+
+        // Initialize the counter at 5
+        let mut init_instruction_data = vec![0];
+        init_instruction_data.extend_from_slice(&5u64.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.push(0); // no bounds
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        // Build an instruction from a variant byte and an optional u64 argument
+        let counter_instruction = |variant: u8, argument: Option<u64>| {
+            let mut data = vec![variant];
+            if let Some(argument) = argument {
+                data.extend_from_slice(&argument.to_le_bytes());
+            }
+            Instruction::new_with_bytes(
+                program_id,
+                &data,
+                vec![
+                    AccountMeta::new(counter_address, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                    AccountMeta::new_readonly(config_address, false),
+                ],
+            )
+        };
+
+        // Step 1: IncrementBy and Decrement
+        println!("Testing increment by and decrement...");
+        let instruction = counter_instruction(4, Some(10));
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap();
+        let instruction = counter_instruction(3, None);
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter.count, 14);
+        println!("✅ Counter moved to: {}", counter.count);
+
+        // Step 2: DecrementBy below zero fails with the underflow error
+        println!("Testing decrement underflow...");
+        let instruction = counter_instruction(5, Some(20));
+        let err = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::Underflow as u32)
+            )
+        );
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter.count, 14);
+        println!("✅ Underflow rejected");
+
+        // Step 3: SetValue to u64::MAX, then incrementing overflows
+        println!("Testing set value and overflow...");
+        let instruction = counter_instruction(7, Some(u64::MAX));
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap();
+        let instruction = counter_instruction(4, Some(1));
+        let err = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::Overflow as u32)
+            )
+        );
+        println!("✅ Overflow rejected");
+
+        // Step 4: Reset back to zero
+        println!("Testing reset...");
+        let instruction = counter_instruction(6, None);
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter.count, 0);
+        println!("✅ Counter reset to: {}", counter.count);
+    }
+
+    #[tokio::test]
+    async fn test_close_counter() {
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        let payer = context.payer.insecure_clone();
+        let recent_blockhash = context.last_blockhash;
+        let (config_address, _) = find_config_address(&program_id);
+
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+        let destination = Pubkey::new_unique();
+        let stranger = Keypair::new();
+
+        let mut init_instruction_data = vec![0];
+        init_instruction_data.extend_from_slice(&7u64.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.push(0); // no bounds
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+        let rent = context
+            .banks_client
+            .get_balance(counter_address)
+            .await
+            .unwrap();
+
+        let close_instruction = |authority: Pubkey| {
+            Instruction::new_with_bytes(
+                program_id,
+                &[8], // 8 = close instruction
+                vec![
+                    AccountMeta::new(counter_address, false),
+                    AccountMeta::new_readonly(authority, true),
+                    AccountMeta::new(destination, false),
+                    AccountMeta::new_readonly(config_address, false),
+                ],
+            )
+        };
+
+        // Step 1: Only the authority can close the counter
+        println!("Testing close by a stranger...");
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[&stranger],
+            recent_blockhash,
+            close_instruction(stranger.pubkey()),
+        )
+        .await;
+        assert!(result.is_err());
+
+        // Step 2: The authority closes the counter and reclaims the rent
+        println!("Testing close by the authority...");
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            close_instruction(payer.pubkey()),
+        )
+        .await
+        .unwrap();
+
+        let account = context
+            .banks_client
+            .get_account(counter_address)
+            .await
+            .expect("Failed to get counter account");
+        assert!(account.is_none());
+        assert_eq!(
+            context.banks_client.get_balance(destination).await.unwrap(),
+            rent
+        );
+        println!("✅ Counter closed, {} lamports reclaimed", rent);
+    }
+
+    #[tokio::test]
+    async fn test_counter_address_must_match_seeds() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        // The address of counter 1 does not match index 0
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 1);
+        let mut init_instruction_data = vec![0];
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.push(0); // no bounds
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        let err = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_counter_already_initialized() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+        let initialize_instruction = |initial_value: u64| {
+            let mut init_instruction_data = vec![0];
+            init_instruction_data.extend_from_slice(&initial_value.to_le_bytes());
+            init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+            init_instruction_data.push(0); // no bounds
+            Instruction::new_with_bytes(
+                program_id,
+                &init_instruction_data,
+                vec![
+                    AccountMeta::new(counter_address, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                    AccountMeta::new_readonly(config_address, false),
+                    AccountMeta::new(find_generation_address(&program_id).0, false),
+                ],
+            )
+        };
+
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction(1),
+        )
+        .await
+        .unwrap();
+
+        // Initializing the same counter again fails and keeps the old value
+        let err = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction(2),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::AlreadyInitialized as u32)
+            )
+        );
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter.count, 1);
+    }
+
+    #[test]
+    fn test_counter_error_codes() {
+        let errors = [
+            CounterError::Overflow,
+            CounterError::Underflow,
+            CounterError::Unauthorized,
+            CounterError::AlreadyInitialized,
+            CounterError::WrongAccountSize,
+            CounterError::Frozen,
+            CounterError::InvalidAccountType,
+            CounterError::OutdatedVersion,
+            CounterError::Paused,
+            CounterError::RateLimited,
+            CounterError::DelegateExpired,
+            CounterError::AllowanceExceeded,
+            CounterError::TokenGated,
+        ];
+        for (code, error) in errors.into_iter().enumerate() {
+            let program_error = ProgramError::from(error);
+            assert_eq!(program_error, ProgramError::Custom(code as u32));
+            assert_eq!(CounterError::try_from(&program_error), Ok(error));
+            assert_eq!(error.to_string(), error.message());
+        }
+        assert_eq!(CounterError::from_code(errors.len() as u32), None);
+        assert!(CounterError::try_from(&ProgramError::InvalidAccountData).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy_counter() {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        );
+
+        // A counter created before accounts were versioned: a bare u64 at a keypair address
+        let legacy_keypair = Keypair::new();
+        program_test.add_account(
+            legacy_keypair.pubkey(),
+            Account {
+                lamports: Rent::default().minimum_balance(CounterAccount::LEGACY_LEN),
+                data: 42u64.to_le_bytes().to_vec(),
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        let increment_instruction = Instruction::new_with_bytes(
+            program_id,
+            &[1],
+            vec![
+                AccountMeta::new(legacy_keypair.pubkey(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+            ],
+        );
+        let migrate_instruction = Instruction::new_with_bytes(
+            program_id,
+            &[9], // 9 = migrate instruction
+            vec![
+                AccountMeta::new(legacy_keypair.pubkey(), true),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(config_address, false),
+            ],
+        );
+
+        // Step 1: Legacy counters must be migrated before they can be used
+        println!("Testing increment of a legacy counter...");
+        let err = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            increment_instruction.clone(),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::OutdatedVersion as u32)
+            )
+        );
+
+        // Step 2: Migrate the counter, keeping its count
+        println!("Testing legacy counter migration...");
+        process(
+            &mut banks_client,
+            &payer,
+            &[&legacy_keypair],
+            recent_blockhash,
+            migrate_instruction,
+        )
+        .await
+        .unwrap();
+
+        let account = banks_client
+            .get_account(legacy_keypair.pubkey())
+            .await
+            .expect("Failed to get counter account")
+            .expect("Counter account not found");
+        assert_eq!(account.data.len(), CounterAccount::LEN);
+        assert_eq!(
+            account.lamports,
+            Rent::default().minimum_balance(CounterAccount::LEN)
+        );
+        let counter = CounterAccount::unpack(&account.data).unwrap();
+        assert_eq!(counter.discriminator, CounterAccount::DISCRIMINATOR);
+        assert_eq!(counter.version, CounterAccount::VERSION);
+        assert_eq!(counter.count, 42);
+        assert_eq!(counter.authority, payer.pubkey());
+        println!("✅ Counter migrated with value: {}", counter.count);
+
+        // Step 3: The migrated counter works as usual
+        let mut increment_by_data = vec![4];
+        increment_by_data.extend_from_slice(&1u64.to_le_bytes());
+        let increment_by_instruction = Instruction::new_with_bytes(
+            program_id,
+            &increment_by_data,
+            increment_instruction.accounts,
+        );
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            increment_by_instruction,
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, legacy_keypair.pubkey()).await;
+        assert_eq!(counter.count, 43);
+    }
+
+    #[tokio::test]
+    async fn test_counter_bounds() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        // Round-robin counter over the slots 1, 2 and 3
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+        let mut init_instruction_data = vec![0];
+        init_instruction_data.extend_from_slice(&3u64.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.push(1); // some bounds
+        init_instruction_data.extend_from_slice(
+            &borsh::to_vec(&CounterBounds {
+                min: 1,
+                max: 3,
+                policy: OverflowPolicy::Wrap,
+            })
+            .unwrap(),
+        );
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        let counter_instruction = |variant: u8, argument: Option<u64>| {
+            let mut data = vec![variant];
+            if let Some(argument) = argument {
+                data.extend_from_slice(&argument.to_le_bytes());
+            }
+            Instruction::new_with_bytes(
+                program_id,
+                &data,
+                vec![
+                    AccountMeta::new(counter_address, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                    AccountMeta::new_readonly(config_address, false),
+                ],
+            )
+        };
+
+        // Step 1: Incrementing past the maximum wraps to the minimum
+        println!("Testing wrap around...");
+        let instruction = counter_instruction(1, None);
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter.count, 1);
+
+        // Decrementing below the minimum wraps to the maximum
+        let instruction = counter_instruction(3, None);
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter.count, 3);
+        println!("✅ Counter wrapped to: {}", counter.count);
+
+        // Step 2: Values outside the bounds cannot be set
+        println!("Testing set value outside the bounds...");
+        let instruction = counter_instruction(7, Some(4));
+        let err = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::Overflow as u32)
+            )
+        );
+
+        // Step 3: Reset goes back to the minimum
+        println!("Testing reset to the minimum...");
+        let instruction = counter_instruction(6, None);
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            instruction,
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter.count, 1);
+        println!("✅ Counter reset to: {}", counter.count);
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let bounds = |policy| CounterBounds {
+            min: 10,
+            max: 19,
+            policy,
+        };
+
+        let error = bounds(OverflowPolicy::Error);
+        assert_eq!(error.increment(18, 1), Ok(19));
+        assert_eq!(error.increment(18, 2), Err(CounterError::Overflow.into()));
+        assert_eq!(error.decrement(11, 2), Err(CounterError::Underflow.into()));
+
+        let saturate = bounds(OverflowPolicy::Saturate);
+        assert_eq!(saturate.increment(18, 5), Ok(19));
+        assert_eq!(saturate.increment(18, u64::MAX), Ok(19));
+        assert_eq!(saturate.decrement(11, 5), Ok(10));
+
+        let wrap = bounds(OverflowPolicy::Wrap);
+        assert_eq!(wrap.increment(19, 1), Ok(10));
+        assert_eq!(wrap.increment(15, 25), Ok(10));
+        assert_eq!(wrap.decrement(10, 1), Ok(19));
+        assert_eq!(wrap.decrement(12, 23), Ok(19));
+
+        // Unbounded counters wrap modulo 2^64
+        let unbounded = CounterBounds {
+            policy: OverflowPolicy::Wrap,
+            ..CounterBounds::default()
+        };
+        assert_eq!(unbounded.increment(u64::MAX, 2), Ok(1));
+        assert_eq!(unbounded.decrement(0, 1), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn test_unpack_outdated_counter() {
+        // A version 1 counter: discriminator, version, count, authority and bump
+        let authority = Pubkey::new_unique();
+        let mut data = CounterAccount::DISCRIMINATOR.to_vec();
+        data.push(1);
+        data.extend_from_slice(&42u64.to_le_bytes());
+        data.extend_from_slice(authority.as_ref());
+        data.push(255);
+
+        assert_eq!(
+            CounterAccount::unpack(&data).unwrap_err(),
+            CounterError::OutdatedVersion.into()
+        );
+
+        let counter = CounterAccount::unpack_outdated(&data).unwrap();
+        assert_eq!(counter.version, CounterAccount::VERSION);
+        assert_eq!(counter.count, 42);
+        assert_eq!(counter.authority, authority);
+        assert_eq!(counter.bump, 255);
+        assert_eq!(counter.bounds(), CounterBounds::default());
+        assert_eq!(counter.history().count(), 0);
+    }
+
+    #[test]
+    fn test_unpack_inconsistent_counter() {
+        // Counts outside the bounds are never written by the program
+        let bounds = CounterBounds {
+            min: 1,
+            max: 3,
+            policy: OverflowPolicy::Wrap,
+        };
+        let data = borsh::to_vec(&CounterAccount::new(5, Pubkey::new_unique(), 0, bounds)).unwrap();
+        assert_eq!(
+            CounterAccount::unpack(&data).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        // Neither are inverted bounds
+        let bounds = CounterBounds {
+            min: 3,
+            max: 1,
+            policy: OverflowPolicy::Wrap,
+        };
+        let data = borsh::to_vec(&CounterAccount::new(2, Pubkey::new_unique(), 0, bounds)).unwrap();
+        assert_eq!(
+            CounterAccount::unpack(&data).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[tokio::test]
+    async fn test_freeze_counter() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+        let mut init_instruction_data = vec![0];
+        init_instruction_data.extend_from_slice(&10u64.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.push(0); // no bounds
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        let counter_instruction = |data: &[u8]| {
+            Instruction::new_with_bytes(
+                program_id,
+                data,
+                vec![
+                    AccountMeta::new(counter_address, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                    AccountMeta::new_readonly(config_address, false),
+                ],
+            )
+        };
+
+        // Step 1: Freeze the counter
+        println!("Testing freeze...");
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            counter_instruction(&[10]), // 10 = freeze instruction
+        )
+        .await
+        .unwrap();
+
+        // Increments and resets fail while frozen, the value can still be read
+        let frozen_error = TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(CounterError::Frozen as u32),
+        );
+        for data in [&[1u8][..], &[6u8][..]] {
+            let err = process(
+                &mut banks_client,
+                &payer,
+                &[],
+                recent_blockhash,
+                counter_instruction(data),
+            )
+            .await
+            .unwrap_err()
+            .unwrap();
+            assert_eq!(err, frozen_error);
+        }
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert!(counter.frozen);
+        assert_eq!(counter.count, 10);
+        println!("✅ Counter frozen at: {}", counter.count);
+
+        // Step 2: Thaw the counter and increment again
+        println!("Testing thaw...");
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            counter_instruction(&[11]), // 11 = thaw instruction
+        )
+        .await
+        .unwrap();
+        let mut increment_by_data = vec![4];
+        increment_by_data.extend_from_slice(&1u64.to_le_bytes());
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            counter_instruction(&increment_by_data),
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert!(!counter.frozen);
+        assert_eq!(counter.count, 11);
+        println!("✅ Counter thawed and incremented to: {}", counter.count);
+    }
+
+    #[tokio::test]
+    async fn test_program_config() {
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        let payer = context.payer.insecure_clone();
+        let recent_blockhash = context.last_blockhash;
+        let (config_address, _) = find_config_address(&program_id);
+        let stranger = Keypair::new();
+
+        // The payer deployed the program
+        let (program_data_address, _) = find_program_data_address(&program_id);
+        context.set_account(
+            &program_data_address,
+            &program_data_account(Some(payer.pubkey())).into(),
+        );
+        let banks_client = &mut context.banks_client;
+        let init_config_instruction = |admin: Pubkey| {
+            let mut init_config_data = vec![12]; // 12 = initialize config instruction
+            init_config_data.extend_from_slice(&5u64.to_le_bytes());
+            Instruction::new_with_bytes(
+                program_id,
+                &init_config_data,
+                vec![
+                    AccountMeta::new(config_address, false),
+                    AccountMeta::new(admin, true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(program_data_address, false),
+                ],
+            )
+        };
+
+        // Step 1: Only the upgrade authority can create the config, with a default step
+        // of 5, and it becomes the admin
+        println!("Testing config creation...");
+        let err = process(
+            banks_client,
+            &payer,
+            &[&stranger],
+            recent_blockhash,
+            init_config_instruction(stranger.pubkey()),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::Unauthorized as u32)
+            )
+        );
+        process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            init_config_instruction(payer.pubkey()),
+        )
+        .await
+        .unwrap();
+        println!("✅ Config created by the upgrade authority only");
+
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+        let mut init_instruction_data = vec![0];
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.push(0); // no bounds
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        let counter_instruction = |variant: u8| {
+            Instruction::new_with_bytes(
+                program_id,
+                &[variant],
+                vec![
+                    AccountMeta::new(counter_address, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                    AccountMeta::new_readonly(config_address, false),
+                ],
+            )
+        };
+        let set_paused_instruction = |admin: Pubkey, paused: bool| {
+            Instruction::new_with_bytes(
+                program_id,
+                &[13, paused as u8], // 13 = set paused instruction
+                vec![
+                    AccountMeta::new(config_address, false),
+                    AccountMeta::new_readonly(admin, true),
+                ],
+            )
+        };
+
+        // Step 2: IncrementCounter uses the default step
+        println!("Testing default step...");
+        process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            counter_instruction(1),
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(banks_client, counter_address).await;
+        assert_eq!(counter.count, 5);
+        println!(
+            "✅ Counter incremented by the default step to: {}",
+            counter.count
+        );
+
+        // Step 3: Only the admin can pause the program
+        println!("Testing global pause...");
+        let result = process(
+            banks_client,
+            &payer,
+            &[&stranger],
+            recent_blockhash,
+            set_paused_instruction(stranger.pubkey(), true),
+        )
+        .await;
+        assert!(result.is_err());
+        process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            set_paused_instruction(payer.pubkey(), true),
+        )
+        .await
+        .unwrap();
+
+        // Counter changes fail while paused
+        let err = process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            counter_instruction(3),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::Paused as u32)
+            )
+        );
+        println!("✅ Counter changes refused while paused");
+
+        // Step 4: Unpause, change the default step and decrement
+        println!("Testing config update...");
+        process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            set_paused_instruction(payer.pubkey(), false),
+        )
+        .await
+        .unwrap();
+        let mut update_config_data = vec![14]; // 14 = update config instruction
+        update_config_data.extend_from_slice(payer.pubkey().as_ref());
+        update_config_data.extend_from_slice(&2u64.to_le_bytes());
+        let update_config_instruction = Instruction::new_with_bytes(
+            program_id,
+            &update_config_data,
+            vec![
+                AccountMeta::new(config_address, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+        );
+        process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            update_config_instruction,
+        )
+        .await
+        .unwrap();
+        // Fresh blockhash so the decrement differs from the attempt made while paused
+        let recent_blockhash = banks_client
+            .get_new_latest_blockhash(&recent_blockhash)
+            .await
+            .unwrap();
+        process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            counter_instruction(3),
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(banks_client, counter_address).await;
+        assert_eq!(counter.count, 3);
+        println!(
+            "✅ Counter decremented by the new default step to: {}",
+            counter.count
+        );
+    }
+
+    // A program that owns counters on behalf of its users, through a PDA "vault" that is
+    // the counter authority. Instruction 0 creates the counter, anything else adds 2.
+    fn process_caller_instruction(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let counter_program = next_account_info(accounts_iter)?;
+        let counter = next_account_info(accounts_iter)?;
+        let vault = next_account_info(accounts_iter)?;
+        let config = next_account_info(accounts_iter)?;
+
+        let (_vault_address, bump) = Pubkey::find_program_address(&[b"vault"], program_id);
+        let vault_seeds: &[&[u8]] = &[b"vault", &[bump]];
+
+        if instruction_data.first() == Some(&0) {
+            let payer = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            let generation = next_account_info(accounts_iter)?;
+            cpi::initialize_counter_signed(
+                &cpi::InitializeCounter {
+                    counter_program,
+                    counter,
+                    payer,
+                    system_program,
+                    authority: vault,
+                    config,
+                    generation,
+                },
+                0,
+                0,
+                None,
+                &[vault_seeds],
+            )
+        } else {
+            cpi::increment_by_signed(
+                &cpi::UpdateCounter {
+                    counter_program,
+                    counter,
+                    authority: vault,
+                    config,
+                    signers: &[],
+                    delegate_record: None,
+                    token_account: None,
+                },
+                2,
+                &[vault_seeds],
+            )?;
+
+            // The new value comes back as return data, and matches a fresh read
+            let count = cpi::get_count_return(counter_program.key)
+                .ok_or(ProgramError::InvalidAccountData)?;
+            let read_count = cpi::get_counter(&cpi::GetCounter {
+                counter_program,
+                counter,
+            })?;
+            if count != read_count {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_counter_cpi_with_pda_authority() {
+        let program_id = Pubkey::new_unique();
+        let caller_program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        );
+        program_test.add_program(
+            "caller_program",
+            caller_program_id,
+            processor!(process_caller_instruction),
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        let (vault_address, _vault_bump) =
+            Pubkey::find_program_address(&[b"vault"], &caller_program_id);
+        let (counter_address, _bump) = find_counter_address(&program_id, &vault_address, 0);
+
+        // Step 1: The caller program creates a counter owned by its vault PDA
+        println!("Testing counter initialization through CPI...");
+        let caller_init_instruction = Instruction::new_with_bytes(
+            caller_program_id,
+            &[0],
+            vec![
+                AccountMeta::new_readonly(program_id, false),
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new_readonly(vault_address, false),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            caller_init_instruction,
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter.authority, vault_address);
+        println!("✅ Counter owned by PDA: {}", counter.authority);
+
+        // Step 2: The caller program increments the counter, signing for its vault
+        println!("Testing counter increment through CPI...");
+        let caller_increment_instruction = Instruction::new_with_bytes(
+            caller_program_id,
+            &[1],
+            vec![
+                AccountMeta::new_readonly(program_id, false),
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new_readonly(vault_address, false),
+                AccountMeta::new_readonly(config_address, false),
+            ],
+        );
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            caller_increment_instruction,
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter.count, 2);
+        println!("✅ Counter incremented through CPI to: {}", counter.count);
+    }
+
+    #[tokio::test]
+    async fn test_get_counter_return_data() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let (config_address, _) = find_config_address(&program_id);
+
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+        let mut init_instruction_data = vec![0];
+        init_instruction_data.extend_from_slice(&41u64.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.push(0); // no bounds
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        // Step 1: Increments return the new count
+        println!("Testing increment return data...");
+        let increment_instruction = Instruction::new_with_bytes(
+            program_id,
+            &[1],
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+            ],
+        );
+        let transaction = Transaction::new_signed_with_payer(
+            &[increment_instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        let result = banks_client
+            .process_transaction_with_metadata(transaction)
+            .await
+            .unwrap();
+        let return_data = result.metadata.unwrap().return_data.unwrap();
+        assert_eq!(return_data.program_id, program_id);
+        assert_eq!(cpi::decode_count(&return_data.data), Some(42));
+        println!("✅ Increment returned: 42");
+
+        // Step 2: GetCounter returns the count without changing the counter
+        println!("Testing get counter...");
+        let get_counter_instruction = Instruction::new_with_bytes(
+            program_id,
+            &[15], // 15 = get counter instruction
+            vec![AccountMeta::new_readonly(counter_address, false)],
+        );
+        let transaction = Transaction::new_signed_with_payer(
+            &[get_counter_instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        let simulation = banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+        assert_eq!(cpi::decode_count(&return_data.data), Some(42));
+        println!("✅ Get counter returned: 42");
+    }
+
+    #[test]
+    fn test_decode_count() {
+        assert_eq!(cpi::decode_count(&42u64.to_le_bytes()), Some(42));
+        // Trailing zeros stripped by the runtime
+        assert_eq!(cpi::decode_count(&[42]), Some(42));
+        assert_eq!(cpi::decode_count(&[]), Some(0));
+        assert_eq!(cpi::decode_count(&[0; 9]), None);
+    }
+
+    #[tokio::test]
+    async fn test_client_instructions() {
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        let payer = context.payer.insecure_clone();
+        let recent_blockhash = context.last_blockhash;
+        let destination = Keypair::new();
+        context.set_account(
+            &find_program_data_address(&program_id).0,
+            &program_data_account(Some(payer.pubkey())).into(),
+        );
+        let banks_client = &mut context.banks_client;
+
+        // Step 1: Config and counter set up through the client builders
+        println!("Testing client builders...");
+        let config_instruction = client::initialize_config(&program_id, &payer.pubkey(), 3);
+        process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            config_instruction,
+        )
+        .await
+        .unwrap();
+
+        let counter_address = client::counter_address(&program_id, &payer.pubkey(), 7);
+        let initialize_instruction =
+            client::initialize_counter(&program_id, &payer.pubkey(), &payer.pubkey(), 7, 10, None);
+        process(
+            banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        for instruction in [
+            client::increment(&program_id, &counter_address, &payer.pubkey()),
+            client::increment_by(&program_id, &counter_address, &payer.pubkey(), 5),
+            client::decrement(&program_id, &counter_address, &payer.pubkey()),
+            client::freeze(&program_id, &counter_address, &payer.pubkey()),
+        ] {
+            process(banks_client, &payer, &[], recent_blockhash, instruction)
+                .await
+                .unwrap();
+        }
+
+        // Step 2: Decode the counter with the client helpers
+        let account = banks_client
+            .get_account(counter_address)
+            .await
+            .unwrap()
+            .unwrap();
+        let counter_data = client::decode_counter(&account.data).unwrap();
+        assert_eq!(counter_data.count(), 15);
+        assert_eq!(counter_data.authority(), &payer.pubkey());
+        assert!(counter_data.is_frozen());
+
+        let config_account = banks_client
+            .get_account(client::config_address(&program_id))
+            .await
+            .unwrap()
+            .unwrap();
+        let config = client::decode_config(&config_account.data).unwrap();
+        assert_eq!(config.admin(), &payer.pubkey());
+        assert_eq!(config.default_step(), 3);
+        println!("✅ Client counter value: {}", counter_data.count());
+
+        // Step 3: Thaw and close
+        let banks_client = &mut context.banks_client;
+        for instruction in [
+            client::thaw(&program_id, &counter_address, &payer.pubkey()),
+            client::close_counter(
+                &program_id,
+                &counter_address,
+                &payer.pubkey(),
+                &destination.pubkey(),
+            ),
+        ] {
+            process(banks_client, &payer, &[], recent_blockhash, instruction)
+                .await
+                .unwrap();
+        }
+        assert!(banks_client
+            .get_account(counter_address)
+            .await
+            .unwrap()
+            .is_none());
+        println!("✅ Client closed the counter");
+    }
+
+    #[test]
+    fn test_client_instruction_layout() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+
+        let instruction =
+            client::initialize_counter(&program_id, &authority, &authority, 2, 5, None);
+        let mut expected = vec![0];
+        expected.extend_from_slice(&5u64.to_le_bytes());
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.push(0); // no bounds
+        assert_eq!(instruction.data, expected);
+        assert_eq!(
+            instruction.accounts[0].pubkey,
+            find_counter_address(&program_id, &authority, 2).0
+        );
+        assert_eq!(
+            instruction.accounts[4].pubkey,
+            find_config_address(&program_id).0
+        );
+
+        let counter = Pubkey::new_unique();
+        let instruction = client::increment_by(&program_id, &counter, &authority, 9);
+        let mut expected = vec![4];
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        assert_eq!(instruction.data, expected);
+        assert!(instruction.accounts[0].is_writable);
+        assert!(instruction.accounts[1].is_signer);
+        assert!(!instruction.accounts[1].is_writable);
+
+        let instruction =
+            client::migrate_legacy_counter(&program_id, &counter, &authority, &authority);
+        assert!(instruction.accounts[0].is_signer);
+        let instruction = client::migrate_counter(&program_id, &counter, &authority, &authority);
+        assert!(!instruction.accounts[0].is_signer);
+
+        // Every builder puts the config where the program looks for it
+        let delegate = Pubkey::new_unique();
+        let instructions = [
+            client::initialize_counter(&program_id, &authority, &authority, 0, 0, None),
+            client::increment(&program_id, &counter, &authority),
+            client::increment_shard_by(&program_id, &counter, 1, &authority, 2),
+            client::decrement(&program_id, &counter, &authority),
+            client::set_value(&program_id, &counter, &authority, 3),
+            client::set_fee(&program_id, &counter, &authority, None),
+            client::close_sharded_counter(&program_id, &counter, &authority, &authority, 2),
+            client::migrate_counter(&program_id, &counter, &authority, &authority),
+            client::initialize_shard(&program_id, &authority, &counter, &authority, 0),
+            client::aggregate(&program_id, &counter, 2),
+            client::contribute(&program_id, &counter, &authority, 4),
+            client::approve_delegate(
+                &program_id,
+                &counter,
+                &authority,
+                &authority,
+                &delegate,
+                None,
+                None,
+            ),
+            client::revoke_delegate(&program_id, &counter, &authority, &delegate, &authority),
+        ];
+        for instruction in instructions {
+            let position = CounterInstruction::unpack(&instruction.data)
+                .unwrap()
+                .config_position()
+                .unwrap();
+            assert_eq!(
+                instruction.accounts[position].pubkey,
+                find_config_address(&program_id).0
+            );
+        }
+    }
+
+    #[test]
+    fn test_instruction_layout() {
+        // The examples from the CounterInstruction format description
+        assert_eq!(
+            CounterInstruction::IncrementBy { amount: 5 }.pack(),
+            [4, 5, 0, 0, 0, 0, 0, 0, 0]
+        );
+        let mut expected = vec![0];
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        expected.push(0);
+        assert_eq!(
+            CounterInstruction::InitializeCounter {
+                initial_value: 7,
+                index: 1,
+                bounds: None,
+            }
+            .pack(),
+            expected
+        );
+
+        // Non canonical encodings are rejected
+        assert!(CounterInstruction::unpack(&[13, 2]).is_err()); // bool other than 0 or 1
+        assert!(CounterInstruction::unpack(&[2, 2]).is_err()); // Option tag other than 0 or 1
+        assert!(CounterInstruction::unpack(&[1, 0]).is_err()); // trailing data
+        assert!(CounterInstruction::unpack(&[30]).is_err()); // unknown variant
+        assert!(CounterInstruction::unpack(&[]).is_err());
+    }
+
+    // Any instruction, with every variant equally likely
+    fn any_instruction() -> impl Strategy<Value = CounterInstruction> {
+        let pubkey = any::<[u8; 32]>().prop_map(Pubkey::new_from_array);
+        let policy = prop_oneof![
+            Just(OverflowPolicy::Error),
+            Just(OverflowPolicy::Saturate),
+            Just(OverflowPolicy::Wrap),
+        ];
+        let bounds = (any::<u64>(), any::<u64>(), policy)
+            .prop_map(|(min, max, policy)| CounterBounds { min, max, policy });
+        let fee = (any::<u64>(), pubkey.clone())
+            .prop_map(|(price, treasury)| CounterFee { price, treasury });
+        let gate = (pubkey.clone(), any::<u64>())
+            .prop_map(|(mint, min_balance)| TokenGate { mint, min_balance });
+        let unit = prop_oneof![Just(WindowUnit::Slots), Just(WindowUnit::Seconds)];
+        let rate_limit =
+            (any::<u64>(), any::<u64>(), unit).prop_map(|(max_increments, window, unit)| {
+                RateLimit {
+                    max_increments,
+                    window,
+                    unit,
+                }
+            });
+        prop_oneof![
+            (any::<u64>(), any::<u64>(), proptest::option::of(bounds)).prop_map(
+                |(initial_value, index, bounds)| CounterInstruction::InitializeCounter {
+                    initial_value,
+                    index,
+                    bounds,
+                }
+            ),
+            Just(CounterInstruction::IncrementCounter),
+            proptest::option::of(pubkey.clone())
+                .prop_map(|new_authority| CounterInstruction::SetAuthority { new_authority }),
+            Just(CounterInstruction::Decrement),
+            any::<u64>().prop_map(|amount| CounterInstruction::IncrementBy { amount }),
+            any::<u64>().prop_map(|amount| CounterInstruction::DecrementBy { amount }),
+            Just(CounterInstruction::Reset),
+            any::<u64>().prop_map(|value| CounterInstruction::SetValue { value }),
+            Just(CounterInstruction::CloseCounter),
+            Just(CounterInstruction::MigrateCounter),
+            Just(CounterInstruction::Freeze),
+            Just(CounterInstruction::Thaw),
+            any::<u64>()
+                .prop_map(|default_step| CounterInstruction::InitializeConfig { default_step }),
+            any::<bool>().prop_map(|paused| CounterInstruction::SetPaused { paused }),
+            (pubkey.clone(), any::<u64>()).prop_map(|(new_admin, default_step)| {
+                CounterInstruction::UpdateConfig {
+                    new_admin,
+                    default_step,
+                }
+            }),
+            Just(CounterInstruction::GetCounter),
+            any::<u8>().prop_map(|shard| CounterInstruction::InitializeShard { shard }),
+            Just(CounterInstruction::Aggregate),
+            Just(CounterInstruction::GetHistory),
+            proptest::option::of(rate_limit)
+                .prop_map(|rate_limit| CounterInstruction::SetRateLimit { rate_limit }),
+            any::<bool>().prop_map(|shared| CounterInstruction::SetShared { shared }),
+            any::<u64>().prop_map(|amount| CounterInstruction::Contribute { amount }),
+            proptest::option::of(fee).prop_map(|fee| CounterInstruction::SetFee { fee }),
+            pubkey
+                .clone()
+                .prop_map(|signer| CounterInstruction::AddSigner { signer }),
+            pubkey
+                .clone()
+                .prop_map(|signer| CounterInstruction::RemoveSigner { signer }),
+            any::<u8>().prop_map(|threshold| CounterInstruction::SetThreshold { threshold }),
+            (
+                pubkey,
+                proptest::option::of(any::<u64>()),
+                proptest::option::of(any::<i64>()),
+            )
+                .prop_map(|(delegate, allowance, expires_at)| {
+                    CounterInstruction::ApproveDelegate {
+                        delegate,
+                        allowance,
+                        expires_at,
+                    }
+                }),
+            Just(CounterInstruction::RevokeDelegate),
+            proptest::option::of(gate).prop_map(|gate| CounterInstruction::SetTokenGate { gate }),
+            Just(CounterInstruction::CloseContribution),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_instruction_round_trip(instruction in any_instruction()) {
+            let data = instruction.pack();
+            prop_assert_eq!(&data, &borsh::to_vec(&instruction).unwrap());
+            prop_assert_eq!(CounterInstruction::unpack(&data).unwrap(), instruction);
+
+            // Extra or missing bytes are never accepted
+            let mut longer = data.clone();
+            longer.push(0);
+            prop_assert!(CounterInstruction::unpack(&longer).is_err());
+            prop_assert!(CounterInstruction::unpack(&data[..data.len() - 1]).is_err());
+        }
+
+        #[test]
+        fn test_instruction_encoding_is_canonical(
+            variant in 0u8..31,
+            rest in proptest::collection::vec(any::<u8>(), 0..80),
+        ) {
+            // Whatever decodes encodes back to the exact same bytes
+            let mut data = vec![variant];
+            data.extend_from_slice(&rest);
+            if let Ok(instruction) = CounterInstruction::unpack(&data) {
+                prop_assert_eq!(instruction.pack(), data);
+            }
+        }
+
+        #[test]
+        fn test_pod_counter_matches_borsh(
+            mut values in any::<[u64; 3]>(),
+            policy in 0u8..3,
+            frozen in any::<bool>(),
+            rate_limit in any::<(u64, u64, bool)>(),
+            fee_price in any::<u64>(),
+            multisig in (0..=MAX_SIGNERS as u8, any::<u8>()),
+            gate in proptest::option::of(any::<u64>()),
+            generation in any::<u64>(),
+            patches in proptest::collection::vec((0..CounterAccount::LEN, any::<u8>()), 0..3),
+        ) {
+            values.sort();
+            let [min, count, max] = values;
+            let policy = [OverflowPolicy::Error, OverflowPolicy::Saturate, OverflowPolicy::Wrap]
+                [policy as usize];
+            let bounds = CounterBounds { min, max, policy };
+            let mut counter_data = CounterAccount::new(count, Pubkey::new_unique(), 0, bounds);
+            counter_data.frozen = frozen;
+            (counter_data.rate_limit_max, counter_data.rate_limit_window) =
+                (rate_limit.0, rate_limit.1);
+            counter_data.rate_limit_unit =
+                if rate_limit.2 { WindowUnit::Seconds } else { WindowUnit::Slots };
+            counter_data.fee_price = fee_price;
+            counter_data.treasury = Pubkey::new_unique();
+            let (signer_count, threshold) = (multisig.0, multisig.1 % (multisig.0 + 1));
+            for signer in &mut counter_data.signers[..signer_count as usize] {
+                *signer = Pubkey::new_unique();
+            }
+            (counter_data.signer_count, counter_data.threshold) = (signer_count, threshold);
+            if let Some(min_balance) = gate {
+                (counter_data.gate_mint, counter_data.gate_min_balance) =
+                    (Pubkey::new_unique(), min_balance);
+            }
+            counter_data.generation = generation;
+
+            // Both views accept the same data, read the same values from it and fail the
+            // same way on anything else
+            let mut data = borsh::to_vec(&counter_data).unwrap();
+            for (offset, value) in patches {
+                data[offset] = value;
+            }
+            match (CounterAccount::unpack(&data), PodCounterAccount::load(&data)) {
+                (Ok(counter_data), Ok(pod)) => {
+                    prop_assert_eq!(pod.count(), counter_data.count());
+                    prop_assert_eq!(pod.authority(), counter_data.authority());
+                    prop_assert_eq!(pod.is_frozen(), counter_data.is_frozen());
+                    prop_assert_eq!(pod.bounds(), counter_data.bounds());
+                    prop_assert_eq!(pod.rate_limit(), counter_data.rate_limit());
+                    prop_assert_eq!(pod.fee(), counter_data.fee());
+                    prop_assert_eq!(pod.multisig(), counter_data.multisig());
+                    prop_assert_eq!(pod.token_gate(), counter_data.token_gate());
+                    prop_assert_eq!(pod.generation(), counter_data.generation());
+                }
+                (Err(error), Err(pod_error)) => prop_assert_eq!(error, pod_error),
+                (counter_data, pod) => prop_assert!(
+                    false,
+                    "Borsh: {:?}, zero-copy: {:?}",
+                    counter_data.map(|_| ()),
+                    pod.map(|_| ())
+                ),
+            }
+
+            // Writes in place are seen by the Borsh view
+            if let Ok(pod) = PodCounterAccount::load_mut(&mut data) {
+                let max = pod.bounds().max;
+                pod.set_count(max);
+                prop_assert_eq!(CounterAccount::unpack(&data).unwrap().count(), max);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sharded_counter() {
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        let payer = context.payer.insecure_clone();
+        let recent_blockhash = context.last_blockhash;
+        let (config_address, _) = find_config_address(&program_id);
+
+        let (counter_address, _bump) = find_counter_address(&program_id, &payer.pubkey(), 0);
+        let mut init_instruction_data = vec![0];
+        init_instruction_data.extend_from_slice(&10u64.to_le_bytes());
+        init_instruction_data.extend_from_slice(&0u64.to_le_bytes());
+        init_instruction_data.push(0); // no bounds
+        let initialize_instruction = Instruction::new_with_bytes(
+            program_id,
+            &init_instruction_data,
+            vec![
+                AccountMeta::new(counter_address, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new_readonly(config_address, false),
+                AccountMeta::new(find_generation_address(&program_id).0, false),
+            ],
+        );
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        // Step 1: Add three shards, in order
+        println!("Testing shard creation...");
+        let shard_addresses: Vec<Pubkey> = (0..3)
+            .map(|shard| find_shard_address(&program_id, &counter_address, shard).0)
+            .collect();
+        let initialize_shard = |shard: u8| {
+            Instruction::new_with_bytes(
+                program_id,
+                &CounterInstruction::InitializeShard { shard }.pack(),
+                vec![
+                    AccountMeta::new(shard_addresses[shard as usize], false),
+                    AccountMeta::new(counter_address, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                    AccountMeta::new_readonly(config_address, false),
+                ],
+            )
+        };
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_shard(1),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+        // The failed transaction used up this blockhash for creating shard 1
+        let recent_blockhash = context
+            .banks_client
+            .get_new_latest_blockhash(&recent_blockhash)
+            .await
+            .unwrap();
+        for shard in 0..3 {
+            process(
+                &mut context.banks_client,
+                &payer,
+                &[],
+                recent_blockhash,
+                initialize_shard(shard),
+            )
+            .await
+            .unwrap();
+        }
+        let account = context
+            .banks_client
+            .get_account(counter_address)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(CounterAccount::unpack(&account.data).unwrap().shards, 3);
+        println!("✅ Counter has 3 shards");
+
+        // Step 2: Increments go to the shards and leave the counter untouched
+        println!("Testing shard increments...");
+        let increment_shard = |shard: usize, amount: u64| {
+            Instruction::new_with_bytes(
+                program_id,
+                &CounterInstruction::IncrementBy { amount }.pack(),
+                vec![
+                    AccountMeta::new(shard_addresses[shard], false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                    AccountMeta::new_readonly(config_address, false),
+                    AccountMeta::new_readonly(counter_address, false),
+                ],
+            )
+        };
+        // Each increment returns the count with only its own shard folded in
+        for (shard, amount, count) in [(0, 2, 12), (2, 5, 15), (0, 1, 13)] {
+            let transaction = Transaction::new_signed_with_payer(
+                &[increment_shard(shard, amount)],
+                Some(&payer.pubkey()),
+                &[&payer],
+                recent_blockhash,
+            );
+            let result = context
+                .banks_client
+                .process_transaction_with_metadata(transaction)
+                .await
+                .unwrap();
+            assert!(result.result.is_ok());
+            let return_data = result.metadata.unwrap().return_data.unwrap();
+            assert_eq!(cpi::decode_count(&return_data.data), Some(count));
+        }
+        assert_eq!(
+            get_counter(&mut context.banks_client, counter_address)
+                .await
+                .count,
+            10
+        );
+        let account = context
+            .banks_client
+            .get_account(shard_addresses[0])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ShardAccount::unpack(&account.data).unwrap().pending, 3);
+        println!("✅ Shard 0 pending increments: 3");
+
+        // Step 3: Fold the shards into the counter, no signature needed
+        println!("Testing aggregation...");
+        let mut aggregate_accounts = vec![
+            AccountMeta::new(counter_address, false),
+            AccountMeta::new_readonly(config_address, false),
+        ];
+        aggregate_accounts.extend(
+            shard_addresses
+                .iter()
+                .map(|shard_address| AccountMeta::new(*shard_address, false)),
+        );
+        let aggregate_instruction = Instruction::new_with_bytes(
+            program_id,
+            &CounterInstruction::Aggregate.pack(),
+            aggregate_accounts,
+        );
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            aggregate_instruction,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_counter(&mut context.banks_client, counter_address)
+                .await
+                .count,
+            18
+        );
+        for shard_address in &shard_addresses {
+            let account = context
+                .banks_client
+                .get_account(*shard_address)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(ShardAccount::unpack(&account.data).unwrap().pending, 0);
+        }
+        // Each shard with pending increments is recorded as one change signed by it
+        let counter_data = get_counter(&mut context.banks_client, counter_address).await;
+        let changes: Vec<(Pubkey, i128, u64)> = counter_data
+            .history()
+            .map(|entry| (*entry.signer(), entry.delta(), entry.value()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![(shard_addresses[0], 3, 13), (shard_addresses[2], 5, 18)]
+        );
+        println!("✅ Counter aggregated to: 18");
+
+        // Step 4: Shards are checked one by one, so together they can pass max. The
+        // count stops there, even under the error policy.
+        println!("Testing aggregation past max...");
+        let bounded_address = client::counter_address(&program_id, &payer.pubkey(), 1);
+        let bounds = CounterBounds {
+            min: 0,
+            max: 20,
+            policy: OverflowPolicy::Error,
+        };
+        let mut instructions = vec![client::initialize_counter(
+            &program_id,
+            &payer.pubkey(),
+            &payer.pubkey(),
+            1,
+            0,
+            Some(bounds),
+        )];
+        for shard in 0..2 {
+            instructions.push(client::initialize_shard(
+                &program_id,
+                &payer.pubkey(),
+                &bounded_address,
+                &payer.pubkey(),
+                shard,
+            ));
+            instructions.push(client::increment_shard_by(
+                &program_id,
+                &bounded_address,
+                shard,
+                &payer.pubkey(),
+                15,
+            ));
+        }
+        instructions.push(client::aggregate(&program_id, &bounded_address, 2));
+        for instruction in instructions {
+            process(
+                &mut context.banks_client,
+                &payer,
+                &[],
+                recent_blockhash,
+                instruction,
+            )
+            .await
+            .unwrap();
+        }
+        assert_eq!(
+            get_counter(&mut context.banks_client, bounded_address)
+                .await
+                .count,
+            20
+        );
+        println!("✅ Counter aggregated to its max: 20");
+
+        // Step 5: Closing the counter closes its shards
+        println!("Testing sharded counter close...");
+        let destination = Keypair::new();
+        let mut close_accounts = vec![
+            AccountMeta::new(counter_address, false),
+            AccountMeta::new_readonly(payer.pubkey(), true),
+            AccountMeta::new(destination.pubkey(), false),
+            AccountMeta::new_readonly(config_address, false),
+        ];
+        close_accounts.extend(
+            shard_addresses
+                .iter()
+                .map(|shard_address| AccountMeta::new(*shard_address, false)),
+        );
+        let close_instruction = Instruction::new_with_bytes(
+            program_id,
+            &CounterInstruction::CloseCounter.pack(),
+            close_accounts,
+        );
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            close_instruction,
+        )
+        .await
+        .unwrap();
+        for address in shard_addresses.iter().chain([&counter_address]) {
+            assert!(context
+                .banks_client
+                .get_account(*address)
+                .await
+                .unwrap()
+                .is_none());
+        }
+        println!("✅ Counter and shards closed");
+    }
+
+    #[tokio::test]
+    async fn test_counter_history() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        let counter_address = client::counter_address(&program_id, &payer.pubkey(), 0);
+        let initialize_instruction =
+            client::initialize_counter(&program_id, &payer.pubkey(), &payer.pubkey(), 0, 10, None);
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        // Step 1: Make more changes than the history holds
+        println!("Testing history recording...");
+        let mut instructions: Vec<Instruction> = (1..=10)
+            .map(|amount| {
+                client::increment_by(&program_id, &counter_address, &payer.pubkey(), amount)
+            })
+            .collect();
+        instructions.push(client::decrement_by(
+            &program_id,
+            &counter_address,
+            &payer.pubkey(),
+            60,
+        ));
+        for instruction in instructions {
+            process(
+                &mut banks_client,
+                &payer,
+                &[],
+                recent_blockhash,
+                instruction,
+            )
+            .await
+            .unwrap();
+        }
+        let counter_data = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(counter_data.count(), 5);
+        assert_eq!(counter_data.changes(), 11);
+
+        // Only the last HISTORY_LEN changes are kept, oldest first
+        let history: Vec<HistoryEntry> = counter_data.history().copied().collect();
+        assert_eq!(history.len(), HISTORY_LEN);
+        let deltas: Vec<i128> = history.iter().map(|entry| entry.delta()).collect();
+        assert_eq!(deltas, [4, 5, 6, 7, 8, 9, 10, -60]);
+        let values: Vec<u64> = history.iter().map(|entry| entry.value()).collect();
+        assert_eq!(values, [20, 25, 31, 38, 46, 55, 65, 5]);
+        assert!(history
+            .iter()
+            .all(|entry| entry.signer() == &payer.pubkey()));
+        assert!(history
+            .windows(2)
+            .all(|pair| pair[0].slot() <= pair[1].slot()));
+        println!("✅ History holds the last {} changes", HISTORY_LEN);
+
+        // Step 2: GetHistory returns the same entries
+        println!("Testing get history...");
+        let transaction = Transaction::new_signed_with_payer(
+            &[client::get_history(&program_id, &counter_address)],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        let simulation = banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+        assert_eq!(client::decode_history(&return_data.data), Some(history));
+        println!("✅ Get history returned {} entries", HISTORY_LEN);
+    }
+
+    #[test]
+    fn test_decode_history() {
+        let entry = HistoryEntry {
+            slot: 3,
+            unix_timestamp: 1_700_000_000,
+            signer: Pubkey::new_unique(),
+            delta: -2,
+            value: 0,
+        };
+        let data = borsh::to_vec(&vec![entry; 2]).unwrap();
+        assert_eq!(cpi::decode_history(&data), Some(vec![entry; 2]));
+
+        // Trailing zeros stripped by the runtime, here the last value
+        let stripped = &data[..data.len() - 8];
+        assert_eq!(cpi::decode_history(stripped), Some(vec![entry; 2]));
+        assert_eq!(cpi::decode_history(&[]), Some(vec![]));
+
+        // More entries than a counter keeps, or data after the entries
+        let data = borsh::to_vec(&vec![entry; HISTORY_LEN + 1]).unwrap();
+        assert_eq!(cpi::decode_history(&data), None);
+        let mut data = borsh::to_vec(&vec![entry; 1]).unwrap();
+        data.push(1);
+        assert_eq!(cpi::decode_history(&data), None);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_counter() {
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        let payer = context.payer.insecure_clone();
+        let recent_blockhash = context.last_blockhash;
+
+        let counter_address = client::counter_address(&program_id, &payer.pubkey(), 0);
+        let initialize_instruction =
+            client::initialize_counter(&program_id, &payer.pubkey(), &payer.pubkey(), 0, 0, None);
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        // Step 1: A rate limit needs a window
+        println!("Testing rate limit validation...");
+        let set_rate_limit = |max_increments: u64, window: u64| {
+            client::set_rate_limit(
+                &program_id,
+                &counter_address,
+                &payer.pubkey(),
+                Some(RateLimit {
+                    max_increments,
+                    window,
+                    unit: WindowUnit::Slots,
+                }),
+            )
+        };
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            set_rate_limit(2, 0),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+        println!("✅ Empty window rejected");
+
+        // Step 2: Two increments per 100 slots
+        println!("Testing rate limited increments...");
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            set_rate_limit(2, 100),
+        )
+        .await
+        .unwrap();
+        let increment_by = |amount: u64| {
+            client::increment_by(&program_id, &counter_address, &payer.pubkey(), amount)
+        };
+        for amount in [1, 2] {
+            process(
+                &mut context.banks_client,
+                &payer,
+                &[],
+                recent_blockhash,
+                increment_by(amount),
+            )
             .await
-            .expect("Failed to get counter account");
+            .unwrap();
+        }
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            increment_by(3),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::RateLimited as u32)
+            )
+        );
 
-        if let Some(account_data) = account {
-            let counter: CounterAccount = CounterAccount::try_from_slice(&account_data.data)
-                .expect("Failed to deserialize counter data");
-            assert_eq!(counter.count, 43);
-            println!("✅ Counter incremented successfully to: {}", counter.count);
+        // Decrements are not limited
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::decrement(&program_id, &counter_address, &payer.pubkey()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_counter(&mut context.banks_client, counter_address)
+                .await
+                .count,
+            2
+        );
+        println!("✅ Third increment in the window rejected");
+
+        // Step 3: The next window allows increments again
+        println!("Testing the next window...");
+        let slot = context.banks_client.get_root_slot().await.unwrap();
+        context.warp_to_slot(slot + 100).unwrap();
+        let recent_blockhash = context
+            .banks_client
+            .get_new_latest_blockhash(&recent_blockhash)
+            .await
+            .unwrap();
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            increment_by(3),
+        )
+        .await
+        .unwrap();
+        let counter_data = get_counter(&mut context.banks_client, counter_address).await;
+        assert_eq!(counter_data.count, 5);
+        assert_eq!(
+            counter_data.rate_limit(),
+            Some(RateLimit {
+                max_increments: 2,
+                window: 100,
+                unit: WindowUnit::Slots,
+            })
+        );
+        println!("✅ Counter incremented in the next window to: 5");
+
+        // Step 4: Rate limited counters cannot be sharded
+        println!("Testing shards of a rate limited counter...");
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::initialize_shard(
+                &program_id,
+                &payer.pubkey(),
+                &counter_address,
+                &payer.pubkey(),
+                0,
+            ),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+        println!("✅ Shard rejected");
+    }
+
+    #[tokio::test]
+    async fn test_counter_contributions() {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        );
+        let alice = Keypair::new();
+        let bob = Keypair::new();
+        for user in [&alice, &bob] {
+            program_test.add_account(
+                user.pubkey(),
+                Account {
+                    lamports: 1_000_000_000,
+                    ..Account::default()
+                },
+            );
+        }
+        let mut context = program_test.start_with_context().await;
+        let payer = context.payer.insecure_clone();
+        let recent_blockhash = context.last_blockhash;
+
+        let counter_address = client::counter_address(&program_id, &payer.pubkey(), 0);
+        let initialize_instruction =
+            client::initialize_counter(&program_id, &payer.pubkey(), &payer.pubkey(), 0, 100, None);
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        // Step 1: Until the counter is shared only its authority can contribute
+        println!("Testing contributions to an unshared counter...");
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::contribute(&program_id, &counter_address, &payer.pubkey(), 5),
+        )
+        .await
+        .unwrap();
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[&alice],
+            recent_blockhash,
+            client::contribute(&program_id, &counter_address, &alice.pubkey(), 3),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::Unauthorized as u32)
+            )
+        );
+        println!("✅ Stranger contribution rejected");
+
+        // Step 2: Anyone contributes to a shared counter, records are created on the
+        // first contribution and updated afterwards
+        println!("Testing contributions to a shared counter...");
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::set_shared(&program_id, &counter_address, &payer.pubkey(), true),
+        )
+        .await
+        .unwrap();
+        let recent_blockhash = context
+            .banks_client
+            .get_new_latest_blockhash(&recent_blockhash)
+            .await
+            .unwrap();
+        for (user, amount) in [(&alice, 3), (&bob, 10), (&alice, 4)] {
+            process(
+                &mut context.banks_client,
+                &payer,
+                &[user],
+                recent_blockhash,
+                client::contribute(&program_id, &counter_address, &user.pubkey(), amount),
+            )
+            .await
+            .unwrap();
+        }
+        let counter_data = get_counter(&mut context.banks_client, counter_address).await;
+        assert_eq!(counter_data.count, 122);
+        assert_eq!(
+            counter_data.history().last().unwrap().signer(),
+            &alice.pubkey()
+        );
+
+        let mut contributions = Vec::new();
+        for user in [&payer, &alice, &bob] {
+            let address =
+                client::contribution_address(&program_id, &counter_address, &user.pubkey());
+            let account = context
+                .banks_client
+                .get_account(address)
+                .await
+                .unwrap()
+                .unwrap();
+            contributions.push((address, client::decode_contribution(&account.data).unwrap()));
+        }
+        let alice_contribution = &contributions[1].1;
+        assert_eq!(alice_contribution.counter(), &counter_address);
+        assert_eq!(alice_contribution.total(), 7);
+        assert_eq!(alice_contribution.contributions(), 2);
+        println!("✅ Alice contributed 7 in 2 contributions");
+
+        // Step 3: Rank the contributors
+        let top = client::top_contributors(contributions, 2);
+        let ranking: Vec<(Pubkey, u64)> = top
+            .iter()
+            .map(|(_, contribution)| (*contribution.contributor(), contribution.total()))
+            .collect();
+        assert_eq!(ranking, [(bob.pubkey(), 10), (alice.pubkey(), 7)]);
+        println!("✅ Top contributors: bob, alice");
+
+        // Step 4: Contributions start over once the counter is closed and created again,
+        // even within one transaction
+        println!("Testing contributions to a re-created counter...");
+        let old_generation = get_counter(&mut context.banks_client, counter_address)
+            .await
+            .generation();
+        let transaction = Transaction::new_signed_with_payer(
+            &[
+                client::close_counter(
+                    &program_id,
+                    &counter_address,
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                ),
+                client::initialize_counter(
+                    &program_id,
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    0,
+                    0,
+                    None,
+                ),
+                client::set_shared(&program_id, &counter_address, &payer.pubkey(), true),
+            ],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap();
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[&alice],
+            recent_blockhash,
+            client::contribute(&program_id, &counter_address, &alice.pubkey(), 2),
+        )
+        .await
+        .unwrap();
+        let generation = get_counter(&mut context.banks_client, counter_address)
+            .await
+            .generation();
+        assert_ne!(generation, old_generation);
+        let mut contributions = Vec::new();
+        for user in [&alice, &bob] {
+            let address =
+                client::contribution_address(&program_id, &counter_address, &user.pubkey());
+            let account = context
+                .banks_client
+                .get_account(address)
+                .await
+                .unwrap()
+                .unwrap();
+            contributions.push(client::decode_contribution(&account.data).unwrap());
+        }
+        assert_eq!(contributions[0].generation(), generation);
+        assert_eq!(contributions[0].total(), 2);
+        assert_eq!(contributions[0].contributions(), 1);
+        // Bob's record is left from the closed counter
+        assert_ne!(contributions[1].generation(), generation);
+        println!("✅ Alice starts over at 2");
+
+        // Step 5: Only the contributor closes their record, even once its counter is gone
+        println!("Testing contribution record close...");
+        let bob_address =
+            client::contribution_address(&program_id, &counter_address, &bob.pubkey());
+        let err = process(
+            &mut context.banks_client,
+            &payer,
+            &[&alice],
+            recent_blockhash,
+            Instruction::new_with_bytes(
+                program_id,
+                &CounterInstruction::CloseContribution.pack(),
+                vec![
+                    AccountMeta::new(bob_address, false),
+                    AccountMeta::new_readonly(alice.pubkey(), true),
+                    AccountMeta::new(alice.pubkey(), false),
+                ],
+            ),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(CounterError::Unauthorized as u32)
+            )
+        );
+        let rent = context.banks_client.get_balance(bob_address).await.unwrap();
+        let balance = context
+            .banks_client
+            .get_balance(bob.pubkey())
+            .await
+            .unwrap();
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[&bob],
+            recent_blockhash,
+            client::close_contribution(&program_id, &counter_address, &bob.pubkey(), &bob.pubkey()),
+        )
+        .await
+        .unwrap();
+        assert!(context
+            .banks_client
+            .get_account(bob_address)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            context
+                .banks_client
+                .get_balance(bob.pubkey())
+                .await
+                .unwrap(),
+            balance + rent
+        );
+        println!("✅ Bob reclaimed {} lamports", rent);
+    }
+
+    #[tokio::test]
+    async fn test_counter_fee() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let treasury = Pubkey::new_unique();
+
+        let counter_address = client::counter_address(&program_id, &payer.pubkey(), 0);
+        let initialize_instruction =
+            client::initialize_counter(&program_id, &payer.pubkey(), &payer.pubkey(), 0, 0, None);
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        // Step 1: Charge 1 SOL per increment
+        println!("Testing fee setup...");
+        let fee = CounterFee {
+            price: 1_000_000_000,
+            treasury,
+        };
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::set_fee(&program_id, &counter_address, &payer.pubkey(), Some(fee)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_counter(&mut banks_client, counter_address).await.fee(),
+            Some(fee)
+        );
+        println!("✅ Fee set to {} lamports", fee.price);
+
+        // Step 2: Increments without the treasury or with another one are refused
+        println!("Testing unpaid increments...");
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::increment(&program_id, &counter_address, &payer.pubkey()),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+        );
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::with_fee(
+                client::increment_by(&program_id, &counter_address, &payer.pubkey(), 2),
+                &Pubkey::new_unique(),
+            ),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+        println!("✅ Unpaid increments refused");
+
+        // Step 3: Paid increments move the fee to the treasury
+        println!("Testing paid increments...");
+        for amount in [3, 4] {
+            process(
+                &mut banks_client,
+                &payer,
+                &[],
+                recent_blockhash,
+                client::with_fee(
+                    client::increment_by(&program_id, &counter_address, &payer.pubkey(), amount),
+                    &treasury,
+                ),
+            )
+            .await
+            .unwrap();
+        }
+        assert_eq!(
+            get_counter(&mut banks_client, counter_address).await.count,
+            7
+        );
+        assert_eq!(
+            banks_client.get_balance(treasury).await.unwrap(),
+            2 * fee.price
+        );
+        println!("✅ Treasury received {} lamports", 2 * fee.price);
+
+        // Step 4: Without a fee increments are free again
+        println!("Testing fee removal...");
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::set_fee(&program_id, &counter_address, &payer.pubkey(), None),
+        )
+        .await
+        .unwrap();
+        let recent_blockhash = banks_client
+            .get_new_latest_blockhash(&recent_blockhash)
+            .await
+            .unwrap();
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::increment(&program_id, &counter_address, &payer.pubkey()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_counter(&mut banks_client, counter_address).await.count,
+            8
+        );
+        println!("✅ Counter incremented for free to: 8");
+    }
+
+    #[tokio::test]
+    async fn test_multisig_counter() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let alice = Keypair::new();
+        let bob = Keypair::new();
+        let carol = Keypair::new();
+        let unauthorized = TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(CounterError::Unauthorized as u32),
+        );
+        let invalid_argument =
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument);
+
+        let counter_address = client::counter_address(&program_id, &payer.pubkey(), 0);
+        let initialize_instruction =
+            client::initialize_counter(&program_id, &payer.pubkey(), &payer.pubkey(), 0, 0, None);
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+
+        // Step 1: The authority registers three signers, which do nothing without a threshold
+        println!("Testing signer registration...");
+        for signer in [&alice, &bob, &carol] {
+            process(
+                &mut banks_client,
+                &payer,
+                &[],
+                recent_blockhash,
+                client::add_signer(
+                    &program_id,
+                    &counter_address,
+                    &payer.pubkey(),
+                    signer.pubkey(),
+                ),
+            )
+            .await
+            .unwrap();
+        }
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[&alice],
+            recent_blockhash,
+            client::add_signer(&program_id, &counter_address, &alice.pubkey(), bob.pubkey()),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().unwrap(), unauthorized);
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(
+            counter.signers(),
+            [alice.pubkey(), bob.pubkey(), carol.pubkey()]
+        );
+        assert_eq!(counter.multisig(), None);
+        println!("✅ Three signers registered");
+
+        // Step 2: A threshold beyond the signers is refused, 2 of 3 hands the counter over
+        println!("Testing threshold...");
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::set_threshold(&program_id, &counter_address, &payer.pubkey(), 4),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().unwrap(), invalid_argument);
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::set_threshold(&program_id, &counter_address, &payer.pubkey(), 2),
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(
+            counter.multisig().map(|multisig| multisig.threshold),
+            Some(2)
+        );
+        println!("✅ Counter needs 2 of 3 signers");
+
+        // Step 3: The authority key or one signer alone, even listed twice, is not enough,
+        // and the authority account has to be one of the signers
+        println!("Testing multisig increments...");
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::increment_by(&program_id, &counter_address, &payer.pubkey(), 1),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().unwrap(), unauthorized);
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[&alice],
+            recent_blockhash,
+            client::with_signers(
+                client::increment_by(&program_id, &counter_address, &alice.pubkey(), 2),
+                &[alice.pubkey()],
+            ),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().unwrap(), unauthorized);
+        // Enough signers, but the authority account is not one of them
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[&bob, &carol],
+            recent_blockhash,
+            client::with_signers(
+                client::increment_by(&program_id, &counter_address, &payer.pubkey(), 2),
+                &[bob.pubkey(), carol.pubkey()],
+            ),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().unwrap(), unauthorized);
+        process(
+            &mut banks_client,
+            &payer,
+            &[&alice, &carol],
+            recent_blockhash,
+            client::with_signers(
+                client::increment_by(&program_id, &counter_address, &alice.pubkey(), 3),
+                &[carol.pubkey()],
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_counter(&mut banks_client, counter_address).await.count,
+            3
+        );
+        println!("✅ Counter incremented by 2 of 3 signers to: 3");
+
+        // Step 4: Signers are removed under the same rule, never below the threshold
+        println!("Testing signer removal...");
+        process(
+            &mut banks_client,
+            &payer,
+            &[&alice, &bob],
+            recent_blockhash,
+            client::with_signers(
+                client::remove_signer(
+                    &program_id,
+                    &counter_address,
+                    &alice.pubkey(),
+                    carol.pubkey(),
+                ),
+                &[bob.pubkey()],
+            ),
+        )
+        .await
+        .unwrap();
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[&alice, &bob],
+            recent_blockhash,
+            client::with_signers(
+                client::remove_signer(&program_id, &counter_address, &alice.pubkey(), bob.pubkey()),
+                &[bob.pubkey()],
+            ),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().unwrap(), invalid_argument);
+        assert_eq!(
+            get_counter(&mut banks_client, counter_address)
+                .await
+                .signers(),
+            [alice.pubkey(), bob.pubkey()]
+        );
+        println!("✅ Signer removed, 2 of 2 left");
+
+        // Step 5: A threshold of 0 hands the counter back to its authority key
+        println!("Testing multisig removal...");
+        process(
+            &mut banks_client,
+            &payer,
+            &[&alice, &bob],
+            recent_blockhash,
+            client::with_signers(
+                client::set_threshold(&program_id, &counter_address, &alice.pubkey(), 0),
+                &[bob.pubkey()],
+            ),
+        )
+        .await
+        .unwrap();
+        process(
+            &mut banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::increment_by(&program_id, &counter_address, &payer.pubkey(), 4),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_counter(&mut banks_client, counter_address).await.count,
+            7
+        );
+        println!("✅ Counter incremented by its authority again to: 7");
+    }
+
+    #[tokio::test]
+    async fn test_counter_delegates() {
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        let payer = context.payer.insecure_clone();
+        let recent_blockhash = context.last_blockhash;
+        let delegate = Keypair::new();
+        let counter_error = |error: CounterError| {
+            TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+        };
+
+        let counter_address = client::counter_address(&program_id, &payer.pubkey(), 0);
+        let delegate_address =
+            client::delegate_address(&program_id, &counter_address, &delegate.pubkey());
+        let initialize_instruction =
+            client::initialize_counter(&program_id, &payer.pubkey(), &payer.pubkey(), 0, 0, None);
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+        let increment_by = |amount: u64| {
+            client::with_delegate(
+                client::increment_by(&program_id, &counter_address, &delegate.pubkey(), amount),
+                &counter_address,
+            )
+        };
+        let approve = |allowance: Option<u64>, expires_at: Option<i64>| {
+            client::approve_delegate(
+                &program_id,
+                &payer.pubkey(),
+                &counter_address,
+                &payer.pubkey(),
+                &delegate.pubkey(),
+                allowance,
+                expires_at,
+            )
+        };
+
+        // Step 1: Without a grant the delegate is refused, grants must allow something
+        println!("Testing delegate approval...");
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[&delegate],
+            recent_blockhash,
+            increment_by(10),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            counter_error(CounterError::Unauthorized)
+        );
+        let now = context
+            .banks_client
+            .get_sysvar::<Clock>()
+            .await
+            .unwrap()
+            .unix_timestamp;
+        for (allowance, expires_at) in [(Some(0), None), (None, Some(now))] {
+            let result = process(
+                &mut context.banks_client,
+                &payer,
+                &[],
+                recent_blockhash,
+                approve(allowance, expires_at),
+            )
+            .await;
+            assert_eq!(
+                result.unwrap_err().unwrap(),
+                TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+            );
+        }
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            approve(Some(2), None),
+        )
+        .await
+        .unwrap();
+        println!("✅ Delegate approved with an allowance of 2 increments");
+
+        // Step 2: The allowance counts increments, whatever their amount, until it runs out
+        println!("Testing delegated increments...");
+        for amount in [20, 30] {
+            process(
+                &mut context.banks_client,
+                &payer,
+                &[&delegate],
+                recent_blockhash,
+                increment_by(amount),
+            )
+            .await
+            .unwrap();
+        }
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[&delegate],
+            recent_blockhash,
+            increment_by(1),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            counter_error(CounterError::AllowanceExceeded)
+        );
+        let counter = get_counter(&mut context.banks_client, counter_address).await;
+        assert_eq!(counter.count, 50);
+        assert_eq!(
+            counter.history().last().unwrap().signer(),
+            &delegate.pubkey()
+        );
+        let account = context
+            .banks_client
+            .get_account(delegate_address)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            DelegateAccount::unpack(&account.data).unwrap().allowance(),
+            Some(0)
+        );
+        println!("✅ Counter incremented twice by the delegate to: 50");
+
+        // Step 3: Approving again replaces the grant, which stops at its expiry
+        println!("Testing delegate expiry...");
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            approve(None, Some(now + 100)),
+        )
+        .await
+        .unwrap();
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[&delegate],
+            recent_blockhash,
+            increment_by(4),
+        )
+        .await
+        .unwrap();
+        let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+        clock.unix_timestamp = now + 100;
+        context.set_sysvar(&clock);
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[&delegate],
+            recent_blockhash,
+            increment_by(6),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            counter_error(CounterError::DelegateExpired)
+        );
+        assert_eq!(
+            get_counter(&mut context.banks_client, counter_address)
+                .await
+                .count,
+            54
+        );
+        println!("✅ Expired delegate refused at: 54");
+
+        // Step 4: Revoking closes the record, the delegate is a stranger again
+        println!("Testing delegate revocation...");
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            approve(None, None),
+        )
+        .await
+        .unwrap();
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::revoke_delegate(
+                &program_id,
+                &counter_address,
+                &payer.pubkey(),
+                &delegate.pubkey(),
+                &payer.pubkey(),
+            ),
+        )
+        .await
+        .unwrap();
+        assert!(context
+            .banks_client
+            .get_account(delegate_address)
+            .await
+            .unwrap()
+            .is_none());
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[&delegate],
+            recent_blockhash,
+            increment_by(7),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            counter_error(CounterError::Unauthorized)
+        );
+        println!("✅ Revoked delegate refused");
+
+        // Step 5: Grants end with their counter, and anyone can send the rent of a record
+        // left behind back to its payer
+        println!("Testing delegates of a closed counter...");
+        // A fresh blockhash, an identical approval was sent before
+        let recent_blockhash = context
+            .banks_client
+            .get_new_latest_blockhash(&recent_blockhash)
+            .await
+            .unwrap();
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            approve(None, None),
+        )
+        .await
+        .unwrap();
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::close_counter(
+                &program_id,
+                &counter_address,
+                &payer.pubkey(),
+                &payer.pubkey(),
+            ),
+        )
+        .await
+        .unwrap();
+        let revoke_to = |destination: &Pubkey| {
+            client::revoke_delegate(
+                &program_id,
+                &counter_address,
+                &delegate.pubkey(),
+                &delegate.pubkey(),
+                destination,
+            )
+        };
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[&delegate],
+            recent_blockhash,
+            revoke_to(&delegate.pubkey()),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[],
+            recent_blockhash,
+            client::initialize_counter(&program_id, &payer.pubkey(), &payer.pubkey(), 0, 0, None),
+        )
+        .await
+        .unwrap();
+        let result = process(
+            &mut context.banks_client,
+            &payer,
+            &[&delegate],
+            recent_blockhash,
+            increment_by(1),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            counter_error(CounterError::Unauthorized)
+        );
+        process(
+            &mut context.banks_client,
+            &payer,
+            &[&delegate],
+            recent_blockhash,
+            revoke_to(&payer.pubkey()),
+        )
+        .await
+        .unwrap();
+        assert!(context
+            .banks_client
+            .get_account(delegate_address)
+            .await
+            .unwrap()
+            .is_none());
+        println!("✅ Old grant void, record closed by the delegate");
+    }
+
+    // A token account as the token program lays it out, initialized and holding `amount`
+    fn token_account(token_program: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+        let mut data = vec![0; TokenAccount::LEN];
+        data[..32].copy_from_slice(mint.as_ref());
+        data[32..64].copy_from_slice(owner.as_ref());
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+        data[108] = 1;
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: token_program,
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn test_token_account_unpack() {
+        let (mint, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = token_account(TOKEN_PROGRAM_ID, mint, owner, 7).data;
+        assert_eq!(
+            TokenAccount::unpack(&data),
+            Ok(TokenAccount {
+                mint,
+                owner,
+                amount: 7
+            })
+        );
+
+        // Frozen accounts still count, uninitialized ones do not
+        data[108] = 2;
+        assert!(TokenAccount::unpack(&data).is_ok());
+        data[108] = 0;
+        assert!(TokenAccount::unpack(&data).is_err());
+        data[108] = 1;
+
+        // Token-2022 accounts with extensions are typed after the base layout
+        data.extend_from_slice(&[2, 0, 0, 0, 0]);
+        assert!(TokenAccount::unpack(&data).is_ok());
+        data[TokenAccount::LEN] = 1; // a mint
+        assert!(TokenAccount::unpack(&data).is_err());
+        data[TokenAccount::LEN] = 2;
+        data.resize(355, 0); // a multisig
+        assert!(TokenAccount::unpack(&data).is_err());
+        assert!(TokenAccount::unpack(&data[..TokenAccount::LEN - 1]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_gated_counter() {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "counter_program",
+            program_id,
+            processor!(process_instruction),
+        );
+        let authority = Keypair::new();
+        let alice = Keypair::new();
+        let mint = Pubkey::new_unique();
+        program_test.add_account(
+            alice.pubkey(),
+            Account {
+                lamports: 1_000_000_000,
+                ..Account::default()
+            },
+        );
+        // Token accounts that do and do not qualify for a gate of 10 tokens of `mint`
+        let holding = Pubkey::new_unique();
+        let not_enough = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        let not_a_token = Pubkey::new_unique();
+        let alice_holding = Pubkey::new_unique();
+        let token_accounts = [
+            (holding, TOKEN_PROGRAM_ID, mint, authority.pubkey(), 10),
+            (not_enough, TOKEN_PROGRAM_ID, mint, authority.pubkey(), 9),
+            (
+                other_mint,
+                TOKEN_PROGRAM_ID,
+                Pubkey::new_unique(),
+                authority.pubkey(),
+                10,
+            ),
+            (not_a_token, program_id, mint, authority.pubkey(), 10),
+            (
+                alice_holding,
+                TOKEN_2022_PROGRAM_ID,
+                mint,
+                alice.pubkey(),
+                50,
+            ),
+        ];
+        for (address, token_program, mint, owner, amount) in token_accounts {
+            program_test.add_account(address, token_account(token_program, mint, owner, amount));
+        }
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let token_gated = TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(CounterError::TokenGated as u32),
+        );
+
+        let counter_address = client::counter_address(&program_id, &authority.pubkey(), 0);
+        let initialize_instruction = client::initialize_counter(
+            &program_id,
+            &payer.pubkey(),
+            &authority.pubkey(),
+            0,
+            0,
+            None,
+        );
+        process(
+            &mut banks_client,
+            &payer,
+            &[&authority],
+            recent_blockhash,
+            initialize_instruction,
+        )
+        .await
+        .unwrap();
+        let set_token_gate = |gate: Option<TokenGate>| {
+            client::set_token_gate(&program_id, &counter_address, &authority.pubkey(), gate)
+        };
+
+        // Step 1: A gate needs a balance to hold
+        println!("Testing token gate validation...");
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[&authority],
+            recent_blockhash,
+            set_token_gate(Some(TokenGate {
+                mint,
+                min_balance: 0,
+            })),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+        process(
+            &mut banks_client,
+            &payer,
+            &[&authority],
+            recent_blockhash,
+            set_token_gate(Some(TokenGate {
+                mint,
+                min_balance: 10,
+            })),
+        )
+        .await
+        .unwrap();
+        let counter = get_counter(&mut banks_client, counter_address).await;
+        assert_eq!(
+            counter.token_gate(),
+            Some(TokenGate {
+                mint,
+                min_balance: 10
+            })
+        );
+        println!("✅ Counter gated to holders of 10 tokens");
+
+        // Step 2: Even the authority needs a qualifying token account of its own
+        println!("Testing gated increments...");
+        let increment = client::increment(&program_id, &counter_address, &authority.pubkey());
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[&authority],
+            recent_blockhash,
+            increment.clone(),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().unwrap(), token_gated);
+        for token_account in [not_enough, other_mint, not_a_token, alice_holding] {
+            let result = process(
+                &mut banks_client,
+                &payer,
+                &[&authority],
+                recent_blockhash,
+                client::with_token_account(increment.clone(), &token_account),
+            )
+            .await;
+            assert_eq!(result.unwrap_err().unwrap(), token_gated);
         }
+        process(
+            &mut banks_client,
+            &payer,
+            &[&authority],
+            recent_blockhash,
+            client::with_token_account(increment.clone(), &holding),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_counter(&mut banks_client, counter_address).await.count,
+            1
+        );
+        println!("✅ Counter incremented by a holder to: 1");
+
+        // Step 3: Contributions to a shared counter are gated the same way
+        println!("Testing gated contributions...");
+        process(
+            &mut banks_client,
+            &payer,
+            &[&authority],
+            recent_blockhash,
+            client::set_shared(&program_id, &counter_address, &authority.pubkey(), true),
+        )
+        .await
+        .unwrap();
+        let contribute = client::contribute(&program_id, &counter_address, &alice.pubkey(), 4);
+        let result = process(
+            &mut banks_client,
+            &payer,
+            &[&alice],
+            recent_blockhash,
+            client::with_token_account(contribute.clone(), &holding),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().unwrap(), token_gated);
+        process(
+            &mut banks_client,
+            &payer,
+            &[&alice],
+            recent_blockhash,
+            client::with_token_account(contribute, &alice_holding),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_counter(&mut banks_client, counter_address).await.count,
+            5
+        );
+        println!("✅ Token-2022 holder contributed, count: 5");
+
+        // Step 4: Without the gate no token account is needed
+        println!("Testing token gate removal...");
+        process(
+            &mut banks_client,
+            &payer,
+            &[&authority],
+            recent_blockhash,
+            set_token_gate(None),
+        )
+        .await
+        .unwrap();
+        process(
+            &mut banks_client,
+            &payer,
+            &[&authority],
+            recent_blockhash,
+            client::increment_by(&program_id, &counter_address, &authority.pubkey(), 1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_counter(&mut banks_client, counter_address).await.count,
+            6
+        );
+        println!("✅ Counter incremented without a token to: 6");
     }
 }
@@ -0,0 +1,351 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use counter::{
+    find_config_address, find_contribution_address, find_counter_address, find_delegate_address,
+    find_generation_address, find_program_data_address, find_shard_address, process_instruction,
+    ContributionAccount, CounterAccount, CounterBounds, DelegateAccount, GenerationAccount,
+    OverflowPolicy, ProgramConfig, ShardAccount, TokenAccount, BPF_LOADER_UPGRADEABLE_ID,
+    TOKEN_PROGRAM_ID,
+};
+use libfuzzer_sys::fuzz_target;
+use programs_fuzz::{process, AccountState};
+use solana_program::{clock::Clock, pubkey::Pubkey};
+use std::sync::OnceLock;
+
+const PROGRAM_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+const USERS: usize = 4;
+const INDICES: usize = 2;
+const SHARDS: usize = 2;
+
+// Addresses the program checks against, so the fuzzer can hit them
+struct Keys {
+    config: Pubkey,
+    generation: Pubkey,
+    program_data: Pubkey,
+    counters: Vec<Pubkey>,
+    shards: Vec<Pubkey>,
+    contributions: Vec<Pubkey>,
+    delegates: Vec<Pubkey>,
+}
+
+fn keys() -> &'static Keys {
+    static KEYS: OnceLock<Keys> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        let counters: Vec<Pubkey> = (0..USERS)
+            .flat_map(|user| {
+                (0..INDICES).map(move |index| {
+                    find_counter_address(&PROGRAM_ID, &user_key(user as u8), index as u64).0
+                })
+            })
+            .collect();
+        let shards = counters
+            .iter()
+            .flat_map(|counter| {
+                (0..SHARDS).map(|shard| find_shard_address(&PROGRAM_ID, counter, shard as u8).0)
+            })
+            .collect();
+        let contributions = counters
+            .iter()
+            .flat_map(|counter| {
+                (0..USERS).map(|user| {
+                    find_contribution_address(&PROGRAM_ID, counter, &user_key(user as u8)).0
+                })
+            })
+            .collect();
+        let delegates = counters
+            .iter()
+            .flat_map(|counter| {
+                (0..USERS).map(|user| {
+                    find_delegate_address(&PROGRAM_ID, counter, &user_key(user as u8)).0
+                })
+            })
+            .collect();
+        Keys {
+            config: find_config_address(&PROGRAM_ID).0,
+            generation: find_generation_address(&PROGRAM_ID).0,
+            program_data: find_program_data_address(&PROGRAM_ID).0,
+            counters,
+            shards,
+            contributions,
+            delegates,
+        }
+    })
+}
+
+fn user_key(user: u8) -> Pubkey {
+    Pubkey::new_from_array([user % USERS as u8 + 1; 32])
+}
+
+#[derive(Arbitrary, Debug)]
+enum Key {
+    Program,
+    SystemProgram,
+    TokenProgram,
+    Loader,
+    Config,
+    Generation,
+    ProgramData,
+    Counter(u8),
+    Shard(u8),
+    Contribution(u8),
+    Delegate(u8),
+    User(u8),
+}
+
+impl Key {
+    fn pubkey(&self) -> Pubkey {
+        match self {
+            Key::Program => PROGRAM_ID,
+            Key::SystemProgram => solana_program::system_program::ID,
+            Key::TokenProgram => TOKEN_PROGRAM_ID,
+            Key::Loader => BPF_LOADER_UPGRADEABLE_ID,
+            Key::Config => keys().config,
+            Key::Generation => keys().generation,
+            Key::ProgramData => keys().program_data,
+            Key::Counter(i) => keys().counters[*i as usize % keys().counters.len()],
+            Key::Shard(i) => keys().shards[*i as usize % keys().shards.len()],
+            Key::Contribution(i) => keys().contributions[*i as usize % keys().contributions.len()],
+            Key::Delegate(i) => keys().delegates[*i as usize % keys().delegates.len()],
+            Key::User(user) => user_key(*user),
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum Data {
+    Raw(Vec<u8>),
+    // A well formed counter, config, generation, shard, contribution, delegate, program
+    // data or token account, with one byte optionally overwritten to reach frozen, paused,
+    // pending, outdated, expired and corrupted states
+    Counter {
+        count: u64,
+        authority: u8,
+        bump: u8,
+        min: u64,
+        max: u64,
+        policy: u8,
+        patch: Option<(u8, u8)>,
+    },
+    Config {
+        admin: u8,
+        default_step: u64,
+        bump: u8,
+        patch: Option<(u8, u8)>,
+    },
+    Generation {
+        last: u64,
+        bump: u8,
+        patch: Option<(u8, u8)>,
+    },
+    Shard {
+        counter: u8,
+        shard: u8,
+        bump: u8,
+        patch: Option<(u8, u8)>,
+    },
+    Contribution {
+        counter: u8,
+        // Counters are of generation 0, anything else is left from an earlier one
+        generation: u8,
+        contributor: u8,
+        bump: u8,
+        patch: Option<(u8, u8)>,
+    },
+    Delegate {
+        counter: u8,
+        // Like contributions, anything but 0 is left from an earlier counter
+        generation: u8,
+        delegate: u8,
+        payer: u8,
+        bump: u8,
+        allowance: Option<u64>,
+        expires_at: Option<i64>,
+        patch: Option<(u8, u8)>,
+    },
+    // The loader's program data header, `None` for an immutable program
+    LoaderHeader {
+        upgrade_authority: Option<u8>,
+        patch: Option<(u8, u8)>,
+    },
+    // Users double as mints
+    Token {
+        mint: u8,
+        owner: u8,
+        amount: u64,
+        patch: Option<(u8, u8)>,
+    },
+}
+
+impl Data {
+    fn bytes(&self) -> Vec<u8> {
+        let (mut data, patch) = match self {
+            Data::Raw(data) => return data.clone(),
+            Data::Counter {
+                count,
+                authority,
+                bump,
+                min,
+                max,
+                policy,
+                patch,
+            } => {
+                let policy = match policy % 3 {
+                    0 => OverflowPolicy::Error,
+                    1 => OverflowPolicy::Saturate,
+                    _ => OverflowPolicy::Wrap,
+                };
+                let bounds = CounterBounds {
+                    min: *min,
+                    max: *max,
+                    policy,
+                };
+                let counter_data = CounterAccount::new(*count, user_key(*authority), *bump, bounds);
+                (borsh::to_vec(&counter_data).unwrap(), patch)
+            }
+            Data::Config {
+                admin,
+                default_step,
+                bump,
+                patch,
+            } => {
+                let config = ProgramConfig::new(user_key(*admin), *default_step, *bump);
+                (borsh::to_vec(&config).unwrap(), patch)
+            }
+            Data::Generation { last, bump, patch } => {
+                let mut data = borsh::to_vec(&GenerationAccount::new(*bump)).unwrap();
+                // The last generation handed out follows the discriminator and version
+                data[9..17].copy_from_slice(&last.to_le_bytes());
+                (data, patch)
+            }
+            Data::Shard {
+                counter,
+                shard,
+                bump,
+                patch,
+            } => {
+                let counter = keys().counters[*counter as usize % keys().counters.len()];
+                let shard_data = ShardAccount::new(counter, shard % SHARDS as u8, *bump);
+                (borsh::to_vec(&shard_data).unwrap(), patch)
+            }
+            Data::Contribution {
+                counter,
+                generation,
+                contributor,
+                bump,
+                patch,
+            } => {
+                let counter = keys().counters[*counter as usize % keys().counters.len()];
+                let contribution_data = ContributionAccount::new(
+                    counter,
+                    *generation as u64,
+                    user_key(*contributor),
+                    *bump,
+                );
+                (borsh::to_vec(&contribution_data).unwrap(), patch)
+            }
+            Data::Delegate {
+                counter,
+                generation,
+                delegate,
+                payer,
+                bump,
+                allowance,
+                expires_at,
+                patch,
+            } => {
+                let counter = keys().counters[*counter as usize % keys().counters.len()];
+                let delegate_data = DelegateAccount::new(
+                    counter,
+                    *generation as u64,
+                    user_key(*delegate),
+                    user_key(*payer),
+                    *bump,
+                    *allowance,
+                    *expires_at,
+                );
+                (borsh::to_vec(&delegate_data).unwrap(), patch)
+            }
+            Data::LoaderHeader {
+                upgrade_authority,
+                patch,
+            } => {
+                let mut data = vec![0; 45];
+                data[..4].copy_from_slice(&3u32.to_le_bytes());
+                if let Some(upgrade_authority) = upgrade_authority {
+                    data[12] = 1;
+                    data[13..].copy_from_slice(user_key(*upgrade_authority).as_ref());
+                }
+                (data, patch)
+            }
+            Data::Token {
+                mint,
+                owner,
+                amount,
+                patch,
+            } => {
+                let mut data = vec![0; TokenAccount::LEN];
+                data[..32].copy_from_slice(user_key(*mint).as_ref());
+                data[32..64].copy_from_slice(user_key(*owner).as_ref());
+                data[64..72].copy_from_slice(&amount.to_le_bytes());
+                data[108] = 1;
+                (data, patch)
+            }
+        };
+        if let Some((offset, value)) = patch {
+            let len = data.len();
+            data[*offset as usize % len] = *value;
+        }
+        data
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct Account {
+    key: Key,
+    owner: Key,
+    is_signer: bool,
+    is_writable: bool,
+    executable: bool,
+    // Real balances are far below u64::MAX, so sums of them cannot overflow
+    lamports: u32,
+    data: Data,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    accounts: Vec<Account>,
+    instruction_data: Vec<u8>,
+    // When the instruction runs, for rate limits, delegate expiries and the history
+    slot: u64,
+    unix_timestamp: i64,
+}
+
+// Processing arbitrary accounts and instruction data never panics
+fuzz_target!(|input: Input| {
+    let accounts: Vec<AccountState> = input
+        .accounts
+        .iter()
+        .take(u8::MAX as usize)
+        .map(|account| AccountState {
+            key: account.key.pubkey(),
+            owner: account.owner.pubkey(),
+            is_signer: account.is_signer,
+            is_writable: account.is_writable,
+            executable: account.executable,
+            lamports: account.lamports as u64,
+            data: account.data.bytes(),
+        })
+        .collect();
+    let _ = process(
+        process_instruction,
+        &PROGRAM_ID,
+        &accounts,
+        &input.instruction_data,
+        Clock {
+            slot: input.slot,
+            unix_timestamp: input.unix_timestamp,
+            ..Clock::default()
+        },
+    );
+});
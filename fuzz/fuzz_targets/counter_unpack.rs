@@ -0,0 +1,11 @@
+#![no_main]
+
+use counter::CounterInstruction;
+use libfuzzer_sys::fuzz_target;
+
+// Instruction decoding never panics, and whatever decodes is the canonical encoding
+fuzz_target!(|data: &[u8]| {
+    if let Ok(instruction) = CounterInstruction::unpack(data) {
+        assert_eq!(instruction.pack(), data);
+    }
+});
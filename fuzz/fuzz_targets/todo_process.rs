@@ -0,0 +1,120 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use programs_fuzz::{process, AccountState};
+use solana_program::{clock::Clock, pubkey::Pubkey, sysvar};
+use todo::process_instruction;
+
+const PROGRAM_ID: Pubkey = Pubkey::new_from_array([9; 32]);
+
+fn user_key(user: u8) -> Pubkey {
+    Pubkey::new_from_array([user % 4 + 1; 32])
+}
+
+#[derive(Arbitrary, Debug)]
+enum Key {
+    Program,
+    SystemProgram,
+    Clock,
+    User(u8),
+}
+
+impl Key {
+    fn pubkey(&self) -> Pubkey {
+        match self {
+            Key::Program => PROGRAM_ID,
+            Key::SystemProgram => solana_program::system_program::ID,
+            Key::Clock => sysvar::clock::ID,
+            Key::User(user) => user_key(*user),
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum Data {
+    Raw(Vec<u8>),
+    // A to-do list as the program writes it, followed by unused account space
+    Todos {
+        todos: Vec<(String, bool, u64)>,
+        padding: u16,
+    },
+    Clock {
+        slot: u64,
+        epoch_start_timestamp: i64,
+        epoch: u64,
+        leader_schedule_epoch: u64,
+        unix_timestamp: i64,
+    },
+}
+
+impl Data {
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Data::Raw(data) => data.clone(),
+            Data::Todos { todos, padding } => {
+                let mut data = borsh::to_vec(todos).unwrap();
+                data.resize(data.len() + *padding as usize, 0);
+                data
+            }
+            Data::Clock {
+                slot,
+                epoch_start_timestamp,
+                epoch,
+                leader_schedule_epoch,
+                unix_timestamp,
+            } => [
+                slot.to_le_bytes(),
+                epoch_start_timestamp.to_le_bytes(),
+                epoch.to_le_bytes(),
+                leader_schedule_epoch.to_le_bytes(),
+                unix_timestamp.to_le_bytes(),
+            ]
+            .concat(),
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct Account {
+    key: Key,
+    owner: Key,
+    is_signer: bool,
+    is_writable: bool,
+    executable: bool,
+    // Real balances are far below u64::MAX, so sums of them cannot overflow
+    lamports: u32,
+    data: Data,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    accounts: Vec<Account>,
+    instruction_data: Vec<u8>,
+}
+
+// Processing arbitrary accounts and instruction data never panics
+fuzz_target!(|input: Input| {
+    let accounts: Vec<AccountState> = input
+        .accounts
+        .iter()
+        .take(u8::MAX as usize)
+        .map(|account| AccountState {
+            key: account.key.pubkey(),
+            owner: account.owner.pubkey(),
+            is_signer: account.is_signer,
+            is_writable: account.is_writable,
+            executable: account.executable,
+            lamports: account.lamports as u64,
+            data: account.data.bytes(),
+        })
+        .collect();
+    let _ = process(
+        process_instruction,
+        &PROGRAM_ID,
+        &accounts,
+        &input.instruction_data,
+        // The program reads the clock from the account it is passed
+        Clock::default(),
+    );
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use todo::TodoInstruction;
+
+// Instruction decoding never panics on untrusted input
+fuzz_target!(|data: &[u8]| {
+    let _ = TodoInstruction::unpack(data);
+});
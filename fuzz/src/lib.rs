@@ -0,0 +1,256 @@
+// In-process harness running a program's `process_instruction` the way the runtime does.
+//
+// Accounts are serialized into the loader's input format and handed out through the
+// same `deserialize` the entrypoint uses, so duplicate accounts share their data and
+// `AccountInfo::resize` has the realloc space it expects. Sysvars come from syscall
+// stubs, the clock is the one passed in and rent is the default.
+//
+// CPIs are checked for the privileges the runtime checks: every account must have been
+// passed in, writable only if it was, and signing only if it did or is a PDA of the
+// caller's seeds. Of the programs that can be called only the system program's
+// CreateAccount and Transfer are carried out, with the same checks on the accounts and
+// lamports as the real one. Any other instruction fails.
+
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::{self, ProgramResult, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER},
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_stubs::{self, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+};
+use std::{cell::RefCell, sync::Once};
+
+thread_local! {
+    // The program being run and the clock it sees, for the stubs
+    static CONTEXT: RefCell<(Pubkey, Clock)> = RefCell::new((Pubkey::default(), Clock::default()));
+}
+
+// An account as passed to the program
+pub struct AccountState {
+    pub key: Pubkey,
+    pub owner: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+    pub executable: bool,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+}
+
+type ProcessInstruction = fn(&Pubkey, &[AccountInfo], &[u8]) -> ProgramResult;
+
+// Run one instruction at `clock`. Accounts with a key seen before are passed as
+// duplicates of the first one, like the runtime does. Panics if a successful
+// instruction created or destroyed lamports.
+pub fn process(
+    process_instruction: ProcessInstruction,
+    program_id: &Pubkey,
+    accounts: &[AccountState],
+    instruction_data: &[u8],
+    clock: Clock,
+) -> ProgramResult {
+    install_stubs();
+    CONTEXT.with(|context| *context.borrow_mut() = (*program_id, clock));
+
+    let mut input = serialize(program_id, accounts, instruction_data);
+    let (program_id, account_infos, instruction_data) =
+        unsafe { entrypoint::deserialize(input.as_mut_ptr() as *mut u8) };
+
+    let total_lamports = |account_infos: &[AccountInfo]| -> u128 {
+        unique(account_infos)
+            .map(|account| account.lamports() as u128)
+            .sum()
+    };
+    let lamports_before = total_lamports(&account_infos);
+    let result = process_instruction(program_id, &account_infos, instruction_data);
+    if result.is_ok() {
+        assert_eq!(
+            total_lamports(&account_infos),
+            lamports_before,
+            "lamports were created or destroyed"
+        );
+    }
+    result
+}
+
+// Each account once, skipping duplicates
+fn unique<'a, 'info>(
+    account_infos: &'a [AccountInfo<'info>],
+) -> impl Iterator<Item = &'a AccountInfo<'info>> {
+    account_infos.iter().enumerate().filter_map(|(i, account)| {
+        let first = account_infos
+            .iter()
+            .position(|other| other.key == account.key);
+        (first == Some(i)).then_some(account)
+    })
+}
+
+// Loader input: accounts, instruction data and program id. Returned as u64 words so the
+// buffer has the 8 byte alignment the entrypoint relies on.
+fn serialize(program_id: &Pubkey, accounts: &[AccountState], instruction_data: &[u8]) -> Vec<u64> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&(accounts.len() as u64).to_le_bytes());
+    for (i, account) in accounts.iter().enumerate() {
+        if let Some(first) = accounts[..i]
+            .iter()
+            .position(|other| other.key == account.key)
+        {
+            buf.push(first as u8);
+            buf.extend_from_slice(&[0; 7]);
+            continue;
+        }
+        buf.push(NON_DUP_MARKER);
+        buf.push(account.is_signer as u8);
+        buf.push(account.is_writable as u8);
+        buf.push(account.executable as u8);
+        buf.extend_from_slice(&[0; 4]); // original data length, filled in by deserialize
+        buf.extend_from_slice(account.key.as_ref());
+        buf.extend_from_slice(account.owner.as_ref());
+        buf.extend_from_slice(&account.lamports.to_le_bytes());
+        buf.extend_from_slice(&(account.data.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&account.data);
+        buf.resize(buf.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+        buf.resize(buf.len().next_multiple_of(8), 0);
+        buf.extend_from_slice(&u64::MAX.to_le_bytes()); // rent epoch
+    }
+    buf.extend_from_slice(&(instruction_data.len() as u64).to_le_bytes());
+    buf.extend_from_slice(instruction_data);
+    buf.extend_from_slice(program_id.as_ref());
+
+    let mut words = vec![0u64; buf.len().div_ceil(8)];
+    for (word, bytes) in words.iter_mut().zip(buf.chunks(8)) {
+        let mut padded = [0; 8];
+        padded[..bytes.len()].copy_from_slice(bytes);
+        *word = u64::from_le_bytes(padded);
+    }
+    words
+}
+
+// Syscalls the programs use: sysvars, CPIs and no stub logging
+struct Stubs;
+
+impl SyscallStubs for Stubs {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_log_data(&self, _fields: &[&[u8]]) {}
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let caller = CONTEXT.with(|context| context.borrow().0);
+        let signers = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+        let accounts = instruction
+            .accounts
+            .iter()
+            .map(|meta| {
+                let account = account_infos
+                    .iter()
+                    .find(|account| account.key == &meta.pubkey)
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?;
+                if meta.is_writable && !account.is_writable {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                if meta.is_signer && !account.is_signer && !signers.contains(account.key) {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+                Ok(account)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if instruction.program_id != solana_program::system_program::ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        system_program(instruction, &accounts)
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { (var_addr as *mut Rent).write_unaligned(Rent::default()) };
+        0
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = CONTEXT.with(|context| context.borrow().1.clone());
+        unsafe { (var_addr as *mut Clock).write_unaligned(clock) };
+        0
+    }
+}
+
+// The system program's CreateAccount and Transfer, the only ones the programs call.
+// Errors are the system program's: 0 is AccountAlreadyInUse, 1 ResultWithNegativeLamports.
+fn system_program(instruction: &Instruction, accounts: &[&AccountInfo]) -> ProgramResult {
+    let data = &instruction.data;
+    let signed = |index: usize| instruction.accounts[index].is_signer;
+    let word = |offset: usize| -> Result<u64, ProgramError> {
+        data.get(offset..offset + 8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or(ProgramError::InvalidInstructionData)
+    };
+    let [from, to, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    match data.get(..4) {
+        // CreateAccount { lamports, space, owner }
+        Some([0, 0, 0, 0]) => {
+            if !signed(0) || !signed(1) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            let owner = data
+                .get(20..52)
+                .and_then(|bytes| Pubkey::try_from(bytes).ok())
+                .ok_or(ProgramError::InvalidInstructionData)?;
+            if to.lamports() > 0
+                || !to.data_is_empty()
+                || to.owner != &solana_program::system_program::ID
+            {
+                return Err(ProgramError::Custom(0));
+            }
+            transfer(from, to, word(4)?)?;
+            let space = word(12)?;
+            to.resize(space as usize)?;
+            to.assign(&owner);
+            Ok(())
+        }
+        // Transfer { lamports }
+        Some([2, 0, 0, 0]) => {
+            if !signed(0) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            transfer(from, to, word(4)?)
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+// Only accounts of the system program without data can send lamports
+fn transfer(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    if !from.data_is_empty() || from.owner != &solana_program::system_program::ID {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let remaining = from
+        .lamports()
+        .checked_sub(lamports)
+        .ok_or(ProgramError::Custom(1))?;
+    **from.try_borrow_mut_lamports()? = remaining;
+    let received = to
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    **to.try_borrow_mut_lamports()? = received;
+    Ok(())
+}
+
+fn install_stubs() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        program_stubs::set_syscall_stubs(Box::new(Stubs));
+    });
+}
@@ -1,7 +1,6 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
     entrypoint::ProgramResult,
     msg,
     program::invoke,
@@ -11,8 +10,9 @@ use solana_program::{
     sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
-// Program entrypoint
-entrypoint!(process_instruction);
+// Program entrypoint, left out when the crate is used as a library by other programs
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 
 // Function to route instructions to the correct handler
 pub fn process_instruction(